@@ -0,0 +1,202 @@
+use crate::ast::*;
+use crate::modules::unqualified_name;
+use failure::Error;
+use std::collections::HashMap;
+
+struct Signature<'a> {
+    kind: &'static str,
+    params: usize,
+    location: &'a Location,
+}
+
+/// the number of arguments built in functions accept, as an inclusive range
+fn builtin_arity(name: &str) -> Option<(usize, usize)> {
+    match name {
+        "assert" => Some((3, 3)),
+        "call" => Some((2, usize::MAX)),
+        "mem" | "mem_byte" => Some((1, 2)),
+        "mem_heap_start" => Some((0, 0)),
+        "mem_heap_end" => Some((0, 1)),
+        "+" | "-" | "*" | "/" | "%" => Some((2, usize::MAX)),
+        "==" | "!=" | "<=" | ">=" | "<" | ">" | "&" | "|" | "^" | "<<" | ">>" | "and" | "or" => {
+            Some((2, 2))
+        }
+        "!" | "~" => Some((1, 1)),
+        _ => None,
+    }
+}
+
+fn describe_count(n: usize) -> String {
+    if n == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", n)
+    }
+}
+
+struct ArityChecker<'a> {
+    signatures: HashMap<&'a str, Signature<'a>>,
+    errors: Vec<String>,
+}
+
+impl<'a> ArityChecker<'a> {
+    /// how a definition is best written, unqualified unless that would be ambiguous
+    fn display_name<'b>(&self, name: &'b str) -> &'b str {
+        let short = unqualified_name(name);
+        let same_short = self
+            .signatures
+            .keys()
+            .filter(|n| unqualified_name(n) == short)
+            .count();
+        if same_short > 1 {
+            name
+        } else {
+            short
+        }
+    }
+
+    fn check_call(&mut self, x: &OperationFunctionCall) {
+        let count = x.params.len();
+        // builtins always win over definitions of the same name
+        if let Some((min, max)) = builtin_arity(&x.function_name) {
+            if count < min || count > max {
+                let expected = if min == max {
+                    describe_count(min)
+                } else if max == usize::MAX {
+                    format!("at least {}", describe_count(min))
+                } else {
+                    format!("{} to {} arguments", min, max)
+                };
+                self.errors.push(format!(
+                    "{}: {} expects {} but was called with {}",
+                    x.location, x.function_name, expected, count
+                ));
+            }
+        } else if let Some(s) = self.signatures.get(x.function_name.as_str()) {
+            if s.params != count {
+                self.errors.push(format!(
+                    "{}: {} {} expects {} but was called with {}\n  {}: defined here",
+                    x.location,
+                    s.kind,
+                    self.display_name(&x.function_name),
+                    describe_count(s.params),
+                    count,
+                    s.location
+                ));
+            }
+        }
+    }
+}
+
+/// make sure every call passes as many arguments as the function it calls takes
+pub fn check_arity(app: &App) -> Result<(), Error> {
+    let mut checker = ArityChecker {
+        signatures: HashMap::new(),
+        errors: vec![],
+    };
+    for op in app.children.iter() {
+        let (name, signature) = match op {
+            TopLevelOperation::DefineFunction(f) => (
+                f.name.as_str(),
+                Signature {
+                    kind: "function",
+                    params: f.params.len(),
+                    location: &f.location,
+                },
+            ),
+            TopLevelOperation::ExternalFunction(f) => (
+                f.name.as_str(),
+                Signature {
+                    kind: "extern",
+                    params: f.params.len(),
+                    location: &f.location,
+                },
+            ),
+            _ => continue,
+        };
+        checker.signatures.insert(name, signature);
+    }
+    for op in app.children.iter() {
+        if let TopLevelOperation::DefineFunction(f) = op {
+            for x in f.children.iter() {
+                walk(x, 0, &mut |x, _| {
+                    if let Expression::FunctionCall(x) = x {
+                        checker.check_call(x);
+                    }
+                });
+            }
+        }
+    }
+    if !checker.errors.is_empty() {
+        return Err(format_err!("{}", checker.errors.join("\n")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::{link, Module};
+
+    fn check(source: &str) -> Result<(), String> {
+        let app = crate::parser::parse(source).expect("source parses");
+        check_arity(&app).map_err(|e| e.to_string())
+    }
+
+    fn module(file: &str, source: &str, entry: bool) -> Module {
+        Module {
+            path: vec!["p".to_string(), file.trim_end_matches(".w").to_string()],
+            root: true,
+            entry,
+            app: crate::parser::parse_file(file, source).expect("source parses"),
+        }
+    }
+
+    #[test]
+    fn calls_to_builtins_take_as_many_arguments_as_they_accept() {
+        assert!(check("fn f() { (mem(1) + mem_byte(1, 2)) }").is_ok());
+        assert_eq!(
+            check("fn f() {\n  mem_heap_start(1)\n}").unwrap_err(),
+            "2:3: mem_heap_start expects 0 arguments but was called with 1"
+        );
+        assert_eq!(
+            check("fn f() { mem() }").unwrap_err(),
+            "1:10: mem expects 1 to 2 arguments but was called with 0"
+        );
+        assert_eq!(
+            check("fn f() { call(1) }").unwrap_err(),
+            "1:10: call expects at least 2 arguments but was called with 1"
+        );
+    }
+
+    #[test]
+    fn calls_to_definitions_take_as_many_arguments_as_they_have_params() {
+        assert_eq!(
+            check("fn g(a, b) { a }\nfn f() { g(1) }").unwrap_err(),
+            "2:10: function g expects 2 arguments but was called with 1\n  1:1: defined here"
+        );
+        assert_eq!(
+            check("extern log(x)\nfn f() { log() }").unwrap_err(),
+            "2:10: extern log expects 1 argument but was called with 0\n  1:1: defined here"
+        );
+    }
+
+    #[test]
+    fn linked_definitions_are_named_as_they_were_written() {
+        let error = |b: &str| {
+            let app = link(vec![
+                module("main.w", "pub fn main() { (a::g(1) + b::h()) }", true),
+                module("a.w", "pub fn g() { 1 }", false),
+                module("b.w", b, false),
+            ])
+            .unwrap();
+            check_arity(&app).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error("pub fn h() { 1 }"),
+            "main.w:1:18: function g expects 0 arguments but was called with 1\n  a.w:1:1: defined here"
+        );
+        // only qualified when another module defines the same name
+        assert!(error("pub fn h() { 1 }\nfn g() { 2 }").contains("function p::a::g expects"));
+    }
+}
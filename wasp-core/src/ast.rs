@@ -1,5 +1,24 @@
+use std::fmt;
 use wasmly::DataType;
 
+/// where something was written in the source
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "{}:{}", self.line, self.column)
+        } else {
+            write!(f, "{}:{}:{}", self.file, self.line, self.column)
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct App {
     pub children: Vec<TopLevelOperation>,
@@ -11,12 +30,25 @@ pub enum TopLevelOperation {
     DefineGlobal(Global),
     DefineFunction(FunctionDefinition),
     ExternalFunction(ExternalFunction),
+    Use(UseDeclaration),
+}
+
+/// bring definitions of another module into scope, e.g. `use std::list::{cons, head}`
+#[derive(Debug, Clone)]
+pub struct UseDeclaration {
+    pub path: Vec<String>,
+    pub names: Vec<String>,
+    pub location: Location,
 }
 
 #[derive(Debug, Clone)]
 pub struct Global {
     pub name: String,
+    pub public: bool,
+    /// replaces the definition of the same name instead of conflicting with it
+    pub overrides: bool,
     pub value: GlobalValue,
+    pub location: Location,
 }
 
 #[derive(Debug, Clone)]
@@ -29,19 +61,43 @@ pub enum GlobalValue {
     Struct(StructDefinition),
 }
 
+/// an inline attribute such as `#[allow(unused_variables)]`
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+    pub location: Location,
+}
+
 #[derive(Debug, Clone)]
 pub struct ExternalFunction {
     pub name: String,
+    pub public: bool,
     pub params: Vec<String>,
+    /// type of each param, f64 unless annotated
+    pub param_types: Vec<DataType>,
+    /// None when declared to return nothing with `-> ()`
+    pub output: Option<DataType>,
+    pub attributes: Vec<Attribute>,
+    pub location: Location,
 }
 
 #[derive(Debug, Clone)]
 pub struct FunctionDefinition {
     pub name: String,
     pub exported: bool,
+    /// replaces the definition of the same name instead of conflicting with it
+    pub overrides: bool,
+    /// substitute the body at every call instead of calling it
+    pub inline: bool,
     pub params: Vec<String>,
-    pub output: Option<String>,
+    /// type of each param, f64 unless annotated
+    pub param_types: Vec<DataType>,
+    /// None when declared to return nothing with `-> ()`
+    pub output: Option<DataType>,
     pub children: Vec<Expression>,
+    pub attributes: Vec<Attribute>,
+    pub location: Location,
 }
 
 #[derive(Debug, Clone)]
@@ -58,15 +114,27 @@ pub struct StructDefinition {
 pub struct OperationFunctionCall {
     pub function_name: String,
     pub params: Vec<Expression>,
+    pub location: Location,
 }
 
 #[derive(Debug, Clone)]
-pub struct OperationRecur {}
+pub struct OperationIdentifier {
+    pub name: String,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone)]
+pub struct OperationRecur {
+    pub location: Location,
+}
 
 #[derive(Debug, Clone)]
 pub struct OperationAssignment {
     pub id: String,
+    /// declared with `mut` so it can be assigned again later
+    pub mutable: bool,
     pub value: Box<Expression>,
+    pub location: Location,
 }
 
 #[derive(Debug, Clone)]
@@ -93,10 +161,104 @@ pub enum Expression {
     Assignment(OperationAssignment),
     TextLiteral(String),
     SymbolLiteral(String),
-    Identifier(String),
+    Identifier(OperationIdentifier),
     FunctionCall(OperationFunctionCall),
     Number(f64),
     Recur(OperationRecur),
     Loop(OperationLoop),
     FnSig(OperationFnSig),
 }
+
+/// visit an expression and everything in it in the order it runs, with how
+/// many loops deep it is. An assignment is visited after its value, once the
+/// local it binds holds something.
+pub fn walk<'a>(e: &'a Expression, loops: usize, f: &mut impl FnMut(&'a Expression, usize)) {
+    match e {
+        Expression::Assignment(x) => {
+            walk(&x.value, loops, f);
+            f(e, loops);
+        }
+        Expression::FunctionCall(x) => {
+            f(e, loops);
+            for p in x.params.iter() {
+                walk(p, loops, f);
+            }
+        }
+        Expression::IfStatement(x) => {
+            f(e, loops);
+            walk(&x.condition, loops, f);
+            for x in x.if_true.iter().chain(x.if_false.iter().flatten()) {
+                walk(x, loops, f);
+            }
+        }
+        Expression::Loop(x) => {
+            f(e, loops);
+            for x in x.expressions.iter() {
+                walk(x, loops + 1, f);
+            }
+        }
+        Expression::TextLiteral(_)
+        | Expression::SymbolLiteral(_)
+        | Expression::Identifier(_)
+        | Expression::Number(_)
+        | Expression::Recur(_)
+        | Expression::FnSig(_) => f(e, loops),
+    }
+}
+
+/// every assignment made within some expressions, in the order they run
+pub fn assignments(e: &[Expression]) -> Vec<&OperationAssignment> {
+    let mut assignments = vec![];
+    for x in e.iter() {
+        walk(x, 0, &mut |x, _| {
+            if let Expression::Assignment(a) = x {
+                assignments.push(a);
+            }
+        });
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(source: &str) -> Vec<Expression> {
+        let app = crate::parser::parse(source).expect("source parses");
+        match app.children.into_iter().next() {
+            Some(TopLevelOperation::DefineFunction(f)) => f.children,
+            _ => panic!("not a function"),
+        }
+    }
+
+    #[test]
+    fn walks_visit_in_the_order_code_runs() {
+        let e = body("fn f(x) { y = foo(x) loop { if y { z = 1 recur } else { y } } }");
+        let mut visited = vec![];
+        for x in e.iter() {
+            walk(x, 0, &mut |x, loops| {
+                visited.push(match x {
+                    Expression::Assignment(a) => format!("{}= {}", a.id, loops),
+                    Expression::FunctionCall(c) => format!("{}() {}", c.function_name, loops),
+                    Expression::Identifier(i) => format!("{} {}", i.name, loops),
+                    Expression::IfStatement(_) => format!("if {}", loops),
+                    Expression::Loop(_) => format!("loop {}", loops),
+                    Expression::Number(n) => format!("{} {}", n, loops),
+                    Expression::Recur(_) => format!("recur {}", loops),
+                    _ => String::new(),
+                })
+            });
+        }
+        assert_eq!(
+            visited,
+            vec![
+                "foo() 0", "x 0", "y= 0", "loop 0", "if 1", "y 1", "1 1", "z= 1", "recur 1", "y 1"
+            ]
+        );
+        let ids = assignments(&e)
+            .iter()
+            .map(|a| a.id.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["y", "z"]);
+    }
+}
@@ -0,0 +1,711 @@
+use crate::ir::*;
+use wasmly::DataType;
+
+const MAGIC_NUMBER: &[u8] = b"\0asm";
+const VERSION: &[u8] = &[1, 0, 0, 0];
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_TABLE: u8 = 4;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_ELEMENT: u8 = 9;
+const SECTION_CODE: u8 = 10;
+const SECTION_DATA: u8 = 11;
+
+const SECTION_NAMES: &[&str] = &[
+    "custom", "type", "import", "function", "table", "memory", "global", "export", "start",
+    "element", "code", "data",
+];
+
+/// the part of the name section naming functions
+const NAMES_FUNCTIONS: u8 = 1;
+
+const DESC_FUNCTION: u8 = 0;
+const DESC_MEMORY: u8 = 2;
+const FUNC: u8 = 0x60;
+const ANYFUNC: u8 = 0x70;
+const EMPTY: u8 = 0x40;
+const LIMIT_MIN_MAX: u8 = 1;
+const LIMIT_SHARED_MIN_MAX: u8 = 3;
+
+const BLOCK: u8 = 0x02;
+const LOOP: u8 = 0x03;
+const IF: u8 = 0x04;
+const ELSE: u8 = 0x05;
+const END: u8 = 0x0b;
+const BR: u8 = 0x0c;
+const RETURN: u8 = 0x0f;
+const CALL: u8 = 0x10;
+const CALL_INDIRECT: u8 = 0x11;
+const RETURN_CALL: u8 = 0x12;
+const RETURN_CALL_INDIRECT: u8 = 0x13;
+const DROP: u8 = 0x1a;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_SET: u8 = 0x21;
+const LOCAL_TEE: u8 = 0x22;
+const GLOBAL_GET: u8 = 0x23;
+const GLOBAL_SET: u8 = 0x24;
+const F64_LOAD: u8 = 0x2b;
+const I32_LOAD8_U: u8 = 0x2d;
+const F64_STORE: u8 = 0x39;
+const I32_STORE8: u8 = 0x3a;
+const I32_CONST: u8 = 0x41;
+const I64_CONST: u8 = 0x42;
+const F32_CONST: u8 = 0x43;
+const F64_CONST: u8 = 0x44;
+const I32_EQZ: u8 = 0x45;
+const I64_EQZ: u8 = 0x50;
+
+/// write an unsigned LEB128 number
+pub fn uleb(out: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// write a signed LEB128 number
+pub fn sleb(out: &mut Vec<u8>, mut v: i64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn value_type(t: &DataType) -> u8 {
+    match t {
+        DataType::I32 => 0x7f,
+        DataType::I64 => 0x7e,
+        DataType::F32 => 0x7d,
+        DataType::F64 => 0x7c,
+    }
+}
+
+fn block_type(t: &Option<DataType>) -> u8 {
+    t.as_ref().map(value_type).unwrap_or(EMPTY)
+}
+
+fn name(out: &mut Vec<u8>, s: &str) {
+    uleb(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// write a vector, its length followed by its items
+fn vector<T>(out: &mut Vec<u8>, items: &[T], mut item: impl FnMut(&mut Vec<u8>, &T)) {
+    uleb(out, items.len() as u64);
+    for i in items.iter() {
+        item(out, i);
+    }
+}
+
+/// write a section, leaving it out when all it holds is an empty vector
+fn section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+    if content == [0] {
+        return;
+    }
+    out.push(id);
+    uleb(out, content.len() as u64);
+    out.extend_from_slice(content);
+}
+
+fn binary_opcode(op: BinaryOp, t: &DataType) -> u8 {
+    use BinaryOp::*;
+    match (t, op) {
+        (DataType::I32, Eq) => 0x46,
+        (DataType::I32, Ne) => 0x47,
+        (DataType::I32, Lt) => 0x48,
+        (DataType::I32, Gt) => 0x4a,
+        (DataType::I32, Le) => 0x4c,
+        (DataType::I32, Ge) => 0x4e,
+        (DataType::I64, Eq) => 0x51,
+        (DataType::I64, Ne) => 0x52,
+        (DataType::I64, Lt) => 0x53,
+        (DataType::I64, Gt) => 0x55,
+        (DataType::I64, Le) => 0x57,
+        (DataType::I64, Ge) => 0x59,
+        (DataType::F32, Eq) => 0x5b,
+        (DataType::F32, Ne) => 0x5c,
+        (DataType::F32, Lt) => 0x5d,
+        (DataType::F32, Gt) => 0x5e,
+        (DataType::F32, Le) => 0x5f,
+        (DataType::F32, Ge) => 0x60,
+        (DataType::F64, Eq) => 0x61,
+        (DataType::F64, Ne) => 0x62,
+        (DataType::F64, Lt) => 0x63,
+        (DataType::F64, Gt) => 0x64,
+        (DataType::F64, Le) => 0x65,
+        (DataType::F64, Ge) => 0x66,
+        (DataType::I32, Add) => 0x6a,
+        (DataType::I32, Sub) => 0x6b,
+        (DataType::I32, Mul) => 0x6c,
+        (DataType::I32, Div) => 0x6d,
+        (DataType::I32, Rem) => 0x6f,
+        (DataType::I32, And) => 0x71,
+        (DataType::I32, Or) => 0x72,
+        (DataType::I32, Xor) => 0x73,
+        (DataType::I32, Shl) => 0x74,
+        (DataType::I32, Shr) => 0x75,
+        (DataType::I64, Add) => 0x7c,
+        (DataType::I64, Sub) => 0x7d,
+        (DataType::I64, Mul) => 0x7e,
+        (DataType::I64, Div) => 0x7f,
+        (DataType::I64, Rem) => 0x81,
+        (DataType::I64, And) => 0x83,
+        (DataType::I64, Or) => 0x84,
+        (DataType::I64, Xor) => 0x85,
+        (DataType::I64, Shl) => 0x86,
+        (DataType::I64, Shr) => 0x87,
+        (DataType::F32, Add) => 0x92,
+        (DataType::F32, Sub) => 0x93,
+        (DataType::F32, Mul) => 0x94,
+        (DataType::F32, Div) => 0x95,
+        (DataType::F64, Add) => 0xa0,
+        (DataType::F64, Sub) => 0xa1,
+        (DataType::F64, Mul) => 0xa2,
+        (DataType::F64, Div) => 0xa3,
+        (t, op) => panic!("no {:?} operator for {:?}", op, t),
+    }
+}
+
+fn convert_opcode(from: &DataType, to: &DataType) -> u8 {
+    match (from, to) {
+        (DataType::I64, DataType::I32) => 0xa7,
+        (DataType::F32, DataType::I32) => 0xa8,
+        (DataType::F64, DataType::I32) => 0xaa,
+        (DataType::I32, DataType::I64) => 0xac,
+        (DataType::F32, DataType::I64) => 0xae,
+        (DataType::F64, DataType::I64) => 0xb0,
+        (DataType::I32, DataType::F32) => 0xb2,
+        (DataType::I64, DataType::F32) => 0xb4,
+        (DataType::F64, DataType::F32) => 0xb6,
+        (DataType::I32, DataType::F64) => 0xb7,
+        (DataType::I64, DataType::F64) => 0xb9,
+        (DataType::F32, DataType::F64) => 0xbb,
+        (from, to) => panic!("no conversion from {:?} to {:?}", from, to),
+    }
+}
+
+fn constant(out: &mut Vec<u8>, v: &Value) {
+    match v {
+        Value::I32(v) => {
+            out.push(I32_CONST);
+            sleb(out, i64::from(*v));
+        }
+        Value::I64(v) => {
+            out.push(I64_CONST);
+            sleb(out, *v);
+        }
+        Value::F32(v) => {
+            out.push(F32_CONST);
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        Value::F64(v) => {
+            out.push(F64_CONST);
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+}
+
+/// the smallest and largest size linear memory can have, in pages
+fn memory_limits(out: &mut Vec<u8>, module: &Module) {
+    out.push(if module.shared_memory {
+        LIMIT_SHARED_MIN_MAX
+    } else {
+        LIMIT_MIN_MAX
+    });
+    uleb(out, u64::from(module.initial_memory));
+    uleb(out, u64::from(module.max_memory));
+}
+
+/// memory accesses are written without offsets, numbers are hinted to be
+/// at a multiple of their size as the layout places them. The hint is only
+/// that, reading a number from anywhere else still works.
+fn memory_immediate(out: &mut Vec<u8>, access: &Access) {
+    out.push(match access {
+        Access::Byte => 0,
+        // log2 of the alignment
+        Access::F64 => crate::layout::NUMBER_ALIGNMENT.trailing_zeros() as u8,
+    });
+    out.push(0);
+}
+
+fn expressions(out: &mut Vec<u8>, e: &[Expr]) {
+    for x in e.iter() {
+        expression(out, x);
+    }
+}
+
+/// write the instructions of an expression
+pub fn expression(out: &mut Vec<u8>, e: &Expr) {
+    match e {
+        Expr::Const(v) => constant(out, v),
+        Expr::LocalGet(i) => {
+            out.push(LOCAL_GET);
+            uleb(out, u64::from(*i));
+        }
+        Expr::LocalSet(i, v) => {
+            expression(out, v);
+            out.push(LOCAL_SET);
+            uleb(out, u64::from(*i));
+        }
+        Expr::LocalTee(i, v) => {
+            expression(out, v);
+            out.push(LOCAL_TEE);
+            uleb(out, u64::from(*i));
+        }
+        Expr::GlobalGet(i) => {
+            out.push(GLOBAL_GET);
+            uleb(out, u64::from(*i));
+        }
+        Expr::GlobalSet(i, v) => {
+            expression(out, v);
+            out.push(GLOBAL_SET);
+            uleb(out, u64::from(*i));
+        }
+        Expr::Load(access, address) => {
+            expression(out, address);
+            out.push(match access {
+                Access::Byte => I32_LOAD8_U,
+                Access::F64 => F64_LOAD,
+            });
+            memory_immediate(out, access);
+        }
+        Expr::Store(access, address, value) => {
+            expression(out, address);
+            expression(out, value);
+            out.push(match access {
+                Access::Byte => I32_STORE8,
+                Access::F64 => F64_STORE,
+            });
+            memory_immediate(out, access);
+        }
+        Expr::Eqz(t, v) => {
+            expression(out, v);
+            out.push(if *t == DataType::I64 {
+                I64_EQZ
+            } else {
+                I32_EQZ
+            });
+        }
+        Expr::Binary(op, t, a, b) => {
+            expression(out, a);
+            expression(out, b);
+            out.push(binary_opcode(*op, t));
+        }
+        Expr::Convert(from, to, v) => {
+            expression(out, v);
+            out.push(convert_opcode(from, to));
+        }
+        Expr::Call(f, args) => {
+            expressions(out, args);
+            out.push(CALL);
+            uleb(out, u64::from(*f));
+        }
+        Expr::CallIndirect(t, callee, args) => {
+            expressions(out, args);
+            expression(out, callee);
+            out.push(CALL_INDIRECT);
+            uleb(out, u64::from(*t));
+            // the only table
+            out.push(0);
+        }
+        Expr::TailCall(call) => match &**call {
+            Expr::Call(f, args) => {
+                expressions(out, args);
+                out.push(RETURN_CALL);
+                uleb(out, u64::from(*f));
+            }
+            Expr::CallIndirect(t, callee, args) => {
+                expressions(out, args);
+                expression(out, callee);
+                out.push(RETURN_CALL_INDIRECT);
+                uleb(out, u64::from(*t));
+                out.push(0);
+            }
+            _ => panic!("only calls can be tail calls"),
+        },
+        Expr::Sequence(e) => expressions(out, e),
+        Expr::Block(t, e) => {
+            out.push(BLOCK);
+            out.push(block_type(t));
+            expressions(out, e);
+            out.push(END);
+        }
+        Expr::If(t, condition, if_true, if_false) => {
+            expression(out, condition);
+            out.push(IF);
+            out.push(block_type(t));
+            expressions(out, if_true);
+            if !if_false.is_empty() {
+                out.push(ELSE);
+                expressions(out, if_false);
+            }
+            out.push(END);
+        }
+        Expr::Loop(t, e) => {
+            out.push(LOOP);
+            out.push(block_type(t));
+            expressions(out, e);
+            out.push(END);
+        }
+        Expr::Br(depth) => {
+            out.push(BR);
+            uleb(out, u64::from(*depth));
+        }
+        Expr::Return(v) => {
+            if let Some(v) = v {
+                expression(out, v);
+            }
+            out.push(RETURN);
+        }
+        Expr::Drop(v) => {
+            expression(out, v);
+            out.push(DROP);
+        }
+    }
+}
+
+fn signature(out: &mut Vec<u8>, s: &Signature) {
+    out.push(FUNC);
+    vector(out, &s.params, |out, t| out.push(value_type(t)));
+    let outputs = s.output.iter().collect::<Vec<&DataType>>();
+    vector(out, &outputs, |out, t| out.push(value_type(t)));
+}
+
+/// the code of a function, runs of locals of the same type are declared together
+pub fn function_body(f: &Function) -> Vec<u8> {
+    let mut runs: Vec<(u32, &DataType)> = vec![];
+    for t in f.locals.iter() {
+        match runs.last_mut() {
+            Some((count, last)) if *last == t => *count += 1,
+            _ => runs.push((1, t)),
+        }
+    }
+    let mut body = vec![];
+    vector(&mut body, &runs, |out, (count, t)| {
+        uleb(out, u64::from(*count));
+        out.push(value_type(t));
+    });
+    expressions(&mut body, &f.body);
+    body.push(END);
+    body
+}
+
+/// encode a module as web assembly
+pub fn encode(module: &Module) -> Vec<u8> {
+    // functions get the types of indirect calls first so those keep their indices
+    let mut types = Module {
+        types: module.types.clone(),
+        ..Module::default()
+    };
+    let import_types = module
+        .imports
+        .iter()
+        .map(|i| types.type_index(i.signature.clone()))
+        .collect::<Vec<u32>>();
+    let function_types = module
+        .functions
+        .iter()
+        .map(|f| types.type_index(f.signature.clone()))
+        .collect::<Vec<u32>>();
+
+    let mut out = MAGIC_NUMBER.to_vec();
+    out.extend_from_slice(VERSION);
+
+    let mut content = vec![];
+    vector(&mut content, &types.types, signature);
+    section(&mut out, SECTION_TYPE, &content);
+
+    let mut content = vec![];
+    uleb(
+        &mut content,
+        (module.imports.len() + usize::from(module.import_memory)) as u64,
+    );
+    for (import, t) in module.imports.iter().zip(import_types.iter()) {
+        name(&mut content, "env");
+        name(&mut content, &import.field);
+        content.push(DESC_FUNCTION);
+        uleb(&mut content, u64::from(*t));
+    }
+    if module.import_memory {
+        name(&mut content, "env");
+        name(&mut content, "memory");
+        content.push(DESC_MEMORY);
+        memory_limits(&mut content, module);
+    }
+    section(&mut out, SECTION_IMPORT, &content);
+
+    let mut content = vec![];
+    vector(&mut content, &function_types, |out, t| {
+        uleb(out, u64::from(*t))
+    });
+    section(&mut out, SECTION_FUNCTION, &content);
+
+    let mut content = vec![];
+    let size = module.table.len() as u64;
+    vector(&mut content, &[size], |out, size| {
+        out.push(ANYFUNC);
+        out.push(LIMIT_MIN_MAX);
+        uleb(out, *size);
+        uleb(out, *size);
+    });
+    section(&mut out, SECTION_TABLE, &content);
+
+    if !module.import_memory {
+        let mut content = vec![];
+        vector(&mut content, &[module], |out, module| {
+            memory_limits(out, module)
+        });
+        section(&mut out, SECTION_MEMORY, &content);
+    }
+
+    let mut content = vec![];
+    vector(&mut content, &module.globals, |out, g| {
+        out.push(value_type(&g.value.data_type()));
+        out.push(if g.mutable { 1 } else { 0 });
+        constant(out, &g.value);
+        out.push(END);
+    });
+    section(&mut out, SECTION_GLOBAL, &content);
+
+    let mut content = vec![];
+    let offset = module.imports.len() as u32;
+    let mut exports = vec![];
+    if let Some(memory) = &module.memory_export {
+        exports.push((memory, DESC_MEMORY, 0));
+    }
+    for (i, f) in module.functions.iter().enumerate() {
+        if let Some(export) = &f.export {
+            exports.push((export, DESC_FUNCTION, offset + i as u32));
+        }
+    }
+    vector(&mut content, &exports, |out, (export, desc, index)| {
+        name(out, export);
+        out.push(*desc);
+        uleb(out, u64::from(*index));
+    });
+    section(&mut out, SECTION_EXPORT, &content);
+
+    if !module.table.is_empty() {
+        let mut content = vec![];
+        vector(&mut content, &[&module.table], |out, table| {
+            // into table 0 starting at 0
+            out.push(0);
+            constant(out, &Value::I32(0));
+            out.push(END);
+            vector(out, table, |out, f| uleb(out, u64::from(*f)));
+        });
+        section(&mut out, SECTION_ELEMENT, &content);
+    }
+
+    let mut content = vec![];
+    vector(&mut content, &module.functions, |out, f| {
+        let body = function_body(f);
+        uleb(out, body.len() as u64);
+        out.extend(body);
+    });
+    section(&mut out, SECTION_CODE, &content);
+
+    let mut content = vec![];
+    vector(&mut content, &module.data, |out, d| {
+        // into memory 0
+        out.push(0);
+        constant(out, &Value::I32(d.offset as i32));
+        out.push(END);
+        uleb(out, d.bytes.len() as u64);
+        out.extend_from_slice(&d.bytes);
+    });
+    section(&mut out, SECTION_DATA, &content);
+
+    if module.names {
+        let mut functions = vec![];
+        let names = module
+            .imports
+            .iter()
+            .map(|i| &i.name)
+            .chain(module.functions.iter().map(|f| &f.name))
+            .enumerate()
+            .collect::<Vec<(usize, &String)>>();
+        vector(&mut functions, &names, |out, (i, n)| {
+            uleb(out, *i as u64);
+            name(out, n);
+        });
+        let mut content = vec![];
+        name(&mut content, "name");
+        content.push(NAMES_FUNCTIONS);
+        uleb(&mut content, functions.len() as u64);
+        content.extend(functions);
+        section(&mut out, SECTION_CUSTOM, &content);
+    }
+
+    out
+}
+
+fn read_uleb(wasm: &[u8], position: &mut usize) -> u64 {
+    let mut v = 0;
+    let mut shift = 0;
+    while let Some(byte) = wasm.get(*position) {
+        *position += 1;
+        v |= u64::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    v
+}
+
+/// the name and size in bytes of every section of an encoded module, custom
+/// sections are named after what they hold
+pub fn section_sizes(wasm: &[u8]) -> Vec<(String, usize)> {
+    let mut sizes = vec![];
+    let mut position = MAGIC_NUMBER.len() + VERSION.len();
+    while position < wasm.len() {
+        let start = position;
+        let id = wasm[position];
+        position += 1;
+        let size = read_uleb(wasm, &mut position) as usize;
+        let content = position;
+        position += size;
+        let name = if id == SECTION_CUSTOM {
+            let mut p = content;
+            let len = read_uleb(wasm, &mut p) as usize;
+            format!(
+                "custom \"{}\"",
+                String::from_utf8_lossy(&wasm[p..(p + len).min(wasm.len())])
+            )
+        } else {
+            SECTION_NAMES
+                .get(id as usize)
+                .unwrap_or(&"unknown")
+                .to_string()
+        };
+        sizes.push((name, position.min(wasm.len()) - start));
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Options;
+    use wasmi::{Engine, ExternType};
+
+    const SOURCE: &str = "pub fn main() { mem(0) }";
+
+    /// the content of the section with an id
+    fn section_content(wasm: &[u8], id: u8) -> Option<&[u8]> {
+        let mut position = MAGIC_NUMBER.len() + VERSION.len();
+        while position < wasm.len() {
+            let found = wasm[position];
+            position += 1;
+            let size = read_uleb(wasm, &mut position) as usize;
+            if found == id {
+                return Some(&wasm[position..position + size]);
+            }
+            position += size;
+        }
+        None
+    }
+
+    fn memory_exports(wasm: &[u8]) -> Vec<String> {
+        let module = wasmi::Module::new(&Engine::default(), wasm).expect("module is valid");
+        module
+            .exports()
+            .filter(|e| matches!(e.ty(), ExternType::Memory(_)))
+            .map(|e| e.name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn memory_is_defined_and_exported() {
+        let options = Options {
+            initial_memory: Some(3),
+            max_memory: Some(7),
+            export_memory_name: Some("heap".to_string()),
+            ..Default::default()
+        };
+        let wasm = crate::testing::compile(SOURCE, &options);
+        assert_eq!(
+            section_content(&wasm, SECTION_MEMORY),
+            Some(&[1, LIMIT_MIN_MAX, 3, 7][..])
+        );
+        assert_eq!(memory_exports(&wasm), vec!["heap"]);
+    }
+
+    #[test]
+    fn imported_memory_is_not_defined() {
+        let options = Options {
+            import_memory: true,
+            ..Default::default()
+        };
+        let wasm = crate::testing::compile(SOURCE, &options);
+        assert_eq!(section_content(&wasm, SECTION_MEMORY), None);
+        let module = wasmi::Module::new(&Engine::default(), &wasm[..]).expect("module is valid");
+        let memories = module
+            .imports()
+            .filter_map(|i| match i.ty() {
+                ExternType::Memory(m) => Some((
+                    i.module().to_string(),
+                    i.name().to_string(),
+                    u32::from(m.initial_pages()),
+                    m.maximum_pages().map(u32::from),
+                )),
+                _ => None,
+            })
+            .collect::<Vec<(String, String, u32, Option<u32>)>>();
+        assert_eq!(
+            memories,
+            vec![("env".to_string(), "memory".to_string(), 2, Some(10))]
+        );
+        // imported memory is still given back to the host
+        assert_eq!(memory_exports(&wasm), vec!["memory"]);
+    }
+
+    #[test]
+    fn shared_memory_has_shared_limits() {
+        let options = Options {
+            shared_memory: true,
+            ..Default::default()
+        };
+        let wasm = crate::testing::compile(SOURCE, &options);
+        assert_eq!(
+            section_content(&wasm, SECTION_MEMORY),
+            Some(&[1, LIMIT_SHARED_MIN_MAX, 2, 10][..])
+        );
+        let options = Options {
+            shared_memory: true,
+            import_memory: true,
+            ..Default::default()
+        };
+        let wasm = crate::testing::compile(SOURCE, &options);
+        let imports = section_content(&wasm, SECTION_IMPORT).expect("an import section");
+        assert!(imports.ends_with(&[DESC_MEMORY, LIMIT_SHARED_MIN_MAX, 2, 10]));
+    }
+
+    #[test]
+    fn memory_without_an_export_is_left_out_of_the_exports() {
+        let app = crate::parser::parse(SOURCE).expect("source parses");
+        let mut module = crate::compiler::compile_with_options(app, &Options::default())
+            .expect("source compiles")
+            .module;
+        module.memory_export = None;
+        let wasm = encode(&module);
+        assert!(memory_exports(&wasm).is_empty());
+        assert!(section_content(&wasm, SECTION_MEMORY).is_some());
+    }
+}
@@ -1,7 +1,10 @@
 use crate::ast::*;
+use crate::ir::{self, Access, BinaryOp, Expr, Value};
+use crate::lint::{Level, Warning};
+use crate::modules::{is_builtin_function, unqualified_name};
 use failure::Error;
-use wasmly::WebAssembly::*;
-use wasmly::*;
+use std::collections::HashMap;
+use wasmly::DataType;
 
 #[derive(PartialEq)]
 enum IdentifierType {
@@ -10,38 +13,128 @@ enum IdentifierType {
     Function,
 }
 
+/// convert the value of an expression from one type to another
+fn convert(e: Expr, from: &DataType, to: &DataType) -> Expr {
+    if from == to {
+        e
+    } else {
+        Expr::Convert(from.clone(), to.clone(), Box::new(e))
+    }
+}
+
+/// evaluate an expression for its effects only
+fn discard(e: Expr) -> Expr {
+    if e.is_unreachable() {
+        e
+    } else {
+        Expr::Drop(Box::new(e))
+    }
+}
+
+/// can a number be written as a constant of any type
+fn is_small_integer(v: f64) -> bool {
+    v.fract() == 0.0 && v >= f64::from(i32::MIN) && v <= f64::from(i32::MAX)
+}
+
+/// a number written in code as a constant of the given type, if it is one
+fn constant(t: &DataType, v: f64) -> Option<Expr> {
+    let integral = is_small_integer(v);
+    match t {
+        DataType::I32 if integral => Some(Expr::Const(Value::I32(v as i32))),
+        DataType::I64 if integral => Some(Expr::Const(Value::I64(v as i64))),
+        DataType::F32 => Some(Expr::Const(Value::F32(v as f32))),
+        DataType::F64 => Some(Expr::Const(Value::F64(v))),
+        _ => None,
+    }
+}
+
+/// the type statics and function handles are written as
+fn global_type(v: f64) -> DataType {
+    if is_small_integer(v) {
+        DataType::I32
+    } else {
+        DataType::F64
+    }
+}
+
+fn is_integer(t: &DataType) -> bool {
+    *t == DataType::I32 || *t == DataType::I64
+}
+
+/// the type values of different types are computed in, whole numbers fit any
+/// type and integers of different sizes are computed as i64
+fn unify(types: Vec<Option<DataType>>) -> Option<DataType> {
+    let mut result = None;
+    for t in types.into_iter().flatten() {
+        result = match result {
+            None => Some(t),
+            Some(r) if r == t => Some(r),
+            Some(ref r) if is_integer(r) && is_integer(&t) => Some(DataType::I64),
+            Some(_) => return Some(DataType::F64),
+        };
+    }
+    result
+}
+
 struct Compiler {
-    wasm: wasmly::App,
+    module: ir::Module,
     ast: crate::ast::App,
     symbols: Vec<String>,
     global_names: Vec<String>,
     global_values: Vec<f64>,
     local_names: Vec<String>,
-    heap_position: f64,
+    local_types: Vec<DataType>,
+    /// for each local, whether it only holds integers because they were inferred
+    local_inferred: Vec<bool>,
+    /// type and inferredness of the locals of the function being compiled
+    /// that are not bound yet
+    local_plan: Vec<(String, DataType, bool)>,
+    layout: crate::layout::Layout,
     function_defs: Vec<TopLevelOperation>,
     function_names: Vec<String>,
-    function_implementations: Vec<wasmly::Function>,
+    /// params and output of every function, in the same order as their names
+    function_types: Vec<(Vec<DataType>, Option<DataType>)>,
+    functions: Vec<ir::Function>,
     non_imported_functions: Vec<String>,
+    /// how many ifs deep we are in the innermost loop
     recur_depth: u32,
-    return_depth: u32,
+    /// whether there is a loop for recur to go back to
+    in_loop: bool,
+    /// output of the function being compiled
+    output: Option<DataType>,
+    /// functions to use in place of others that compile the same
+    aliases: HashMap<String, String>,
+    /// give data identical to data already placed the same address
+    share_data: bool,
+    /// whether the static being placed is only ever read, so it can share
+    /// the data of an identical one
+    share_static: bool,
 }
 
 impl Compiler {
     fn new(app: crate::ast::App) -> Compiler {
         let mut c = Compiler {
-            wasm: wasmly::App::new(vec![]),
+            module: ir::Module::default(),
             ast: app,
             symbols: vec![],
             global_names: vec![],
             global_values: vec![],
             local_names: vec![],
-            heap_position: 4.0, //start at 4 so nothing has 0 address
+            local_types: vec![],
+            local_inferred: vec![],
+            local_plan: vec![],
+            layout: crate::layout::Layout::new(crate::layout::DATA_START),
             function_defs: vec![],
             function_names: vec![],
-            function_implementations: vec![],
+            function_types: vec![],
+            functions: vec![],
             non_imported_functions: vec![],
             recur_depth: 0,
-            return_depth: 1,
+            in_loop: false,
+            output: None,
+            aliases: HashMap::new(),
+            share_data: false,
+            share_static: false,
         };
         c.initialize();
         c
@@ -59,16 +152,19 @@ impl Compiler {
             })
             .collect::<Vec<&ExternalFunction>>();
 
-        let mut imports = vec![];
         for def in import_defs {
             self.function_names.push(def.name.clone());
-            imports.push(Import::ImportFunction(ImportFunction::new(
-                def.name.clone(),
-                def.params.iter().map(|_| DataType::F64).collect(),
-                Some(DataType::F64),
-            )))
+            self.function_types
+                .push((def.param_types.clone(), def.output.clone()));
+            self.module.imports.push(ir::Import {
+                name: def.name.clone(),
+                field: unqualified_name(&def.name).to_string(),
+                signature: ir::Signature {
+                    params: def.param_types.clone(),
+                    output: def.output.clone(),
+                },
+            })
         }
-        self.wasm = wasmly::App::new(imports);
         self.function_defs = self
             .ast
             .children
@@ -90,58 +186,75 @@ impl Compiler {
                 _ => None,
             })
             .collect::<Vec<crate::ast::Global>>();
+        let read_only = crate::read_only::read_only_statics(&self.ast)
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
         for def in global_defs {
+            self.share_static = read_only.contains(&def.name);
             self.global_names.push(def.name.clone());
-            let v = self.get_global_value(&def.value);
+            let v = self.get_global_value(&def.value, &def.name);
             self.global_values.push(v);
         }
     }
 
     fn float_to_bytes(&self, i: f64) -> Vec<u8> {
-        let raw_bytes: [u8; 8] = unsafe { std::mem::transmute(i) };
-        let bytes: Vec<u8> = raw_bytes.to_vec();
-        bytes
+        i.to_le_bytes().to_vec()
     }
 
-    fn create_global_data(&mut self, v: Vec<GlobalValue>) -> f64 {
+    fn create_global_data(
+        &mut self,
+        v: Vec<GlobalValue>,
+        origin: ir::DataOrigin,
+        label: &str,
+    ) -> f64 {
         let mut bytes = vec![];
         for i in 0..v.len() {
-            let v = self.get_global_value(&v[i]);
+            let v = self.get_global_value(&v[i], label);
             let b = self.float_to_bytes(v);
             bytes.extend_from_slice(&b);
         }
-        self.create_data(bytes)
+        // identical tables of numbers only read from can be read from the same place
+        let share = self.share_static;
+        self.create_data(bytes, origin, label.to_string(), share)
     }
 
     fn get_symbol_value(&mut self, t: &str) -> f64 {
         // no symbol has the value 0
-        let v = self.symbols.iter().enumerate().find(|x| &x.1 == &t);
+        let v = self.symbols.iter().enumerate().find(|x| x.1 == t);
         if let Some(i) = v {
-            return i.0 as f64 + 1.0;
+            i.0 as f64 + 1.0
         } else {
             self.symbols.push(t.to_string());
-            return self.symbols.len() as f64;
+            self.symbols.len() as f64
         }
     }
 
-    fn get_global_value(&mut self, v: &GlobalValue) -> f64 {
+    /// the value of a static, labelling any data it places with the static's name
+    fn get_global_value(&mut self, v: &GlobalValue, label: &str) -> f64 {
         match v {
             GlobalValue::Symbol(t) => self.get_symbol_value(t),
             GlobalValue::Number(t) => *t,
-            GlobalValue::Text(t) => self.get_or_create_text_data(&t),
-            GlobalValue::Data(t) => self.create_global_data(t.clone()),
+            GlobalValue::Text(t) => self.get_or_create_text_data(t),
+            GlobalValue::Data(t) => {
+                self.create_global_data(t.clone(), ir::DataOrigin::Static, label)
+            }
             GlobalValue::Struct(s) => {
                 let mut t: Vec<GlobalValue> = vec![];
                 for i in 0..s.members.len() {
                     t.push(GlobalValue::Symbol(s.members[i].name.clone()));
                 }
                 t.push(GlobalValue::Number(0.0));
-                self.create_global_data(t)
+                self.create_global_data(t, ir::DataOrigin::Struct, label)
             }
             GlobalValue::Identifier(t) => {
-                self.resolve_identifier(t)
-                    .expect(&format!("{} is not a valid identifier", &t))
-                    .0
+                match self
+                    .resolve_identifier(t)
+                    .unwrap_or_else(|| panic!("{} is not a valid identifier", &t))
+                {
+                    (idx, IdentifierType::Function) => self.get_handle(idx as u32),
+                    (v, _) => v,
+                }
             }
         }
     }
@@ -152,6 +265,10 @@ impl Compiler {
         for i in 0..self.function_defs.len() {
             if let TopLevelOperation::DefineFunction(function_def) = &self.function_defs[i] {
                 self.function_names.push(function_def.name.clone());
+                self.function_types.push((
+                    function_def.param_types.clone(),
+                    function_def.output.clone(),
+                ));
                 self.non_imported_functions.push(function_def.name.clone());
             }
         }
@@ -159,55 +276,68 @@ impl Compiler {
         // get the basics about our functions loaded into memory
         for i in 0..self.function_defs.len() {
             if let TopLevelOperation::DefineFunction(function_def) = &self.function_defs[i] {
-                let mut function = Function::new();
-                if function_def.exported {
-                    function.with_name(&function_def.name);
-                }
-                function.with_inputs(function_def.params.iter().map(|_| DataType::F64).collect());
-                function.with_output(DataType::F64);
-                self.function_implementations.push(function);
+                let export = if function_def.exported {
+                    Some(unqualified_name(&function_def.name).to_string())
+                } else {
+                    None
+                };
+                self.functions.push(ir::Function {
+                    name: function_def.name.clone(),
+                    export,
+                    file: function_def.location.file.clone(),
+                    inline: function_def.inline,
+                    signature: ir::Signature {
+                        params: function_def.param_types.clone(),
+                        output: function_def.output.clone(),
+                    },
+                    locals: vec![],
+                    body: vec![],
+                });
             }
         }
+    }
 
-        self.wasm.add_table(wasmly::Table::new(
-            self.function_names.len() as u32,
-            self.function_names.len() as u32,
-        ));
+    /// the handle of a function, its position in the table
+    fn get_handle(&mut self, function: u32) -> f64 {
+        let table = &mut self.module.table;
+        match table.iter().position(|f| *f == function) {
+            Some(p) => p as f64,
+            None => {
+                table.push(function);
+                table.len() as f64 - 1.0
+            }
+        }
     }
 
     fn set_heap_start(&mut self) {
         //set global heap once we know what it should be
-        let final_heap_pos = {
-            if self.heap_position % 4.0 != 0.0 {
-                (self.heap_position / 4.0) * 4.0 + 4.0
-            } else {
-                self.heap_position
-            }
-        };
-        self.wasm
-            .add_global(wasmly::Global::new(final_heap_pos as i32, false));
-        self.wasm
-            .add_global(wasmly::Global::new(final_heap_pos as i32, true));
+        let final_heap_pos = self.layout.heap_start();
+        self.module.heap_start = final_heap_pos;
+        self.module.globals.push(ir::Global {
+            mutable: false,
+            value: Value::I32(final_heap_pos as i32),
+        });
+        self.module.globals.push(ir::Global {
+            mutable: true,
+            value: Value::I32(final_heap_pos as i32),
+        });
     }
 
     fn get_or_create_text_data(&mut self, str: &str) -> f64 {
         let mut bytes: Vec<u8> = str.as_bytes().into();
         bytes.push(0);
-        self.create_data(bytes)
+        let share = self.share_data;
+        self.create_data(bytes, ir::DataOrigin::Text, format!("{:?}", str), share)
     }
 
-    fn create_data(&mut self, bytes: Vec<u8>) -> f64 {
-        let pos = self.heap_position;
-        let size = bytes.len();
-        self.wasm.add_data(Data::new(pos as i32, bytes));
-        let mut final_heap_pos = self.heap_position + (size as f64);
-        // align data to 4
-        // TODO: verify if this actually matters
-        if final_heap_pos % 4.0 != 0.0 {
-            final_heap_pos = (final_heap_pos / 4.0) * 4.0 + 4.0;
-        }
-        self.heap_position = final_heap_pos;
-        pos
+    fn create_data(
+        &mut self,
+        bytes: Vec<u8>,
+        origin: ir::DataOrigin,
+        label: String,
+        share: bool,
+    ) -> f64 {
+        f64::from(self.layout.place(bytes, origin, label, share))
     }
 
     fn resolve_identifier(&self, id: &str) -> Option<(f64, IdentifierType)> {
@@ -218,436 +348,696 @@ impl Compiler {
             return Some((8.0, IdentifierType::Global));
         }
         // look this up in reverse so shadowing works
-        let mut p = self.local_names.iter().rev().position(|r| r == id);
-        if p.is_some() {
+        if let Some(p) = self.local_names.iter().rev().position(|r| r == id) {
             return Some((
-                self.local_names.len() as f64 - 1.0 - p.unwrap() as f64,
+                self.local_names.len() as f64 - 1.0 - p as f64,
                 IdentifierType::Local,
             ));
         }
-        p = self.function_names.iter().position(|r| r == id);
-        if p.is_some() {
-            return Some((p.unwrap() as f64, IdentifierType::Function));
+        let function = self.aliases.get(id).map(|f| f.as_str()).unwrap_or(id);
+        if let Some(p) = self.function_names.iter().position(|r| r == function) {
+            return Some((p as f64, IdentifierType::Function));
         }
-        p = self.global_names.iter().position(|r| r == id);
-        if p.is_some() {
-            return Some((self.global_values[p.unwrap()], IdentifierType::Global));
+        if let Some(p) = self.global_names.iter().position(|r| r == id) {
+            return Some((self.global_values[p], IdentifierType::Global));
         }
         None
     }
 
-    #[allow(clippy::cyclomatic_complexity)]
-    fn process_expression(&mut self, i: usize, e: &Expression) {
+    /// the type of the last of a list of expressions
+    fn last_type(&self, e: &[Expression]) -> Option<DataType> {
+        e.last().and_then(|x| self.expression_type(x))
+    }
+
+    /// the type operands of an operator are computed in
+    fn operand_type(&self, params: &[Expression]) -> Option<DataType> {
+        unify(params.iter().map(|p| self.expression_type(p)).collect())
+    }
+
+    /// the type an expression leaves on the stack, None for whole numbers
+    /// which can be written as a constant of any type and for recur which
+    /// never leaves a value
+    fn expression_type(&self, e: &Expression) -> Option<DataType> {
+        match e {
+            Expression::Number(v) if is_small_integer(*v) => None,
+            Expression::Recur(_) => None,
+            Expression::SymbolLiteral(_) | Expression::TextLiteral(_) | Expression::FnSig(_) => {
+                Some(DataType::I32)
+            }
+            Expression::Identifier(x) => match self.resolve_identifier(&x.name) {
+                Some((idx, IdentifierType::Local)) => Some(self.local_types[idx as usize].clone()),
+                Some((v, _)) => Some(global_type(v)),
+                None => Some(DataType::F64),
+            },
+            Expression::Assignment(x) => match self.resolve_identifier(&x.id) {
+                Some((idx, IdentifierType::Local)) => Some(self.local_types[idx as usize].clone()),
+                _ => match self.local_plan.iter().find(|(name, _, _)| *name == x.id) {
+                    Some((_, t, _)) => Some(t.clone()),
+                    None => Some(self.expression_type(&x.value).unwrap_or(DataType::F64)),
+                },
+            },
+            Expression::IfStatement(x) => Some(self.branch_type(x)),
+            Expression::Loop(x) => Some(self.last_type(&x.expressions).unwrap_or(DataType::F64)),
+            Expression::FunctionCall(x) => Some(self.call_type(x)),
+            _ => Some(DataType::F64),
+        }
+    }
+
+    /// whether an integer an expression leaves on the stack was only inferred
+    /// to be one, such integers keep the meaning of the f64 they stand in for
+    fn is_inferred(&self, e: &Expression) -> bool {
+        let local = |idx: f64| self.local_inferred[idx as usize];
+        match e {
+            Expression::SymbolLiteral(_) | Expression::TextLiteral(_) | Expression::FnSig(_) => {
+                true
+            }
+            Expression::Identifier(x) => match self.resolve_identifier(&x.name) {
+                Some((idx, IdentifierType::Local)) => local(idx),
+                Some((v, _)) => is_small_integer(v),
+                None => false,
+            },
+            Expression::Assignment(x) => match self.resolve_identifier(&x.id) {
+                Some((idx, IdentifierType::Local)) => local(idx),
+                _ => match self.local_plan.iter().find(|(name, _, _)| *name == x.id) {
+                    Some((_, _, inferred)) => *inferred,
+                    None => self.is_inferred(&x.value),
+                },
+            },
+            Expression::IfStatement(x) => {
+                x.if_true
+                    .last()
+                    .map(|e| self.is_inferred(e))
+                    .unwrap_or(false)
+                    || x.if_false
+                        .as_ref()
+                        .and_then(|f| f.last())
+                        .map(|e| self.is_inferred(e))
+                        .unwrap_or(false)
+            }
+            Expression::Loop(x) => x
+                .expressions
+                .last()
+                .map(|e| self.is_inferred(e))
+                .unwrap_or(false),
+            Expression::FunctionCall(x) => match x.function_name.as_str() {
+                "==" | "!=" | "<=" | ">=" | "<" | ">" | "!" | "and" | "or" | "mem_byte"
+                | "mem_heap_start" | "mem_heap_end" => true,
+                "mem" => x.params.len() == 2,
+                "+" | "-" => self.any_inferred(&x.params),
+                "%" | "&" | "|" | "^" | "<<" | ">>" | "~" => self.bitwise_type(&x.params).1,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn any_inferred(&self, params: &[Expression]) -> bool {
+        params.iter().any(|p| self.is_inferred(p))
+    }
+
+    /// the type bitwise operators compute in and whether it was inferred,
+    /// declared i32 keep their type and anything else is computed as an i64
+    fn bitwise_type(&self, params: &[Expression]) -> (DataType, bool) {
+        match self.operand_type(params) {
+            Some(DataType::I32) if !self.any_inferred(params) => (DataType::I32, false),
+            Some(DataType::I64) if !self.any_inferred(params) => (DataType::I64, false),
+            _ => (DataType::I64, true),
+        }
+    }
+
+    /// the type arithmetic operators compute in. Inferred integers are added
+    /// and subtracted as an i64, but multiplied and divided as the f64 they
+    /// stand in for since products can grow past what an i64 holds.
+    fn arithmetic_type(&self, x: &OperationFunctionCall) -> DataType {
+        let t = self.operand_type(&x.params).unwrap_or(DataType::F64);
+        if is_integer(&t) && self.any_inferred(&x.params) {
+            match x.function_name.as_str() {
+                "+" | "-" => DataType::I64,
+                _ => DataType::F64,
+            }
+        } else {
+            t
+        }
+    }
+
+    /// whether a value is known while compiling, as numbers, statics and
+    /// function handles are
+    fn is_constant(&self, e: &Expression) -> bool {
+        match e {
+            Expression::Number(_) => true,
+            Expression::Identifier(x) => matches!(
+                self.resolve_identifier(&x.name),
+                Some((_, IdentifierType::Global)) | Some((_, IdentifierType::Function))
+            ),
+            _ => false,
+        }
+    }
+
+    /// whether a value assigned to a local over and over, as in a loop, can
+    /// keep growing faster than by a constant each time. Adding up anything
+    /// but a single value and constants, like doubling a local or adding two
+    /// locals, can.
+    fn grows(&self, e: &Expression) -> bool {
+        match e {
+            Expression::FunctionCall(x) if x.function_name == "+" || x.function_name == "-" => {
+                let mut values = x.params.iter().filter(|p| !self.is_constant(p));
+                match (values.next(), values.next()) {
+                    (Some(_), Some(_)) => true,
+                    (Some(v), None) => self.grows(v),
+                    _ => false,
+                }
+            }
+            Expression::Assignment(x) => self.grows(&x.value),
+            Expression::IfStatement(x) => {
+                x.if_true.last().map(|e| self.grows(e)).unwrap_or(false)
+                    || x.if_false
+                        .as_ref()
+                        .and_then(|f| f.last())
+                        .map(|e| self.grows(e))
+                        .unwrap_or(false)
+            }
+            Expression::Loop(x) => x.expressions.last().map(|e| self.grows(e)).unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    fn branch_type(&self, x: &OperationIfStatement) -> DataType {
+        let if_true = self.last_type(&x.if_true);
+        match &x.if_false {
+            Some(f) => unify(vec![if_true, self.last_type(f)]),
+            None => if_true,
+        }
+        .unwrap_or(DataType::F64)
+    }
+
+    fn call_type(&self, x: &OperationFunctionCall) -> DataType {
+        match x.function_name.as_str() {
+            "+" | "-" | "*" | "/" => self.arithmetic_type(x),
+            "%" | "&" | "|" | "^" | "<<" | ">>" | "~" => self.bitwise_type(&x.params).0,
+            "==" | "!=" | "<=" | ">=" | "<" | ">" | "!" | "and" | "or" | "mem_byte"
+            | "mem_heap_start" | "mem_heap_end" => DataType::I32,
+            "mem" if x.params.len() == 2 => DataType::I32,
+            "call" => match x.params.first() {
+                Some(Expression::FnSig(sig)) => sig.output.clone().unwrap_or(DataType::F64),
+                _ => DataType::F64,
+            },
+            name if is_builtin_function(name) => DataType::F64,
+            name => match self.resolve_identifier(name) {
+                Some((idx, IdentifierType::Function)) => self.function_types[idx as usize]
+                    .1
+                    .clone()
+                    .unwrap_or(DataType::F64),
+                _ => DataType::F64,
+            },
+        }
+    }
+
+    /// the value of an expression known while compiling
+    fn constant_value(&mut self, e: &Expression) -> Option<f64> {
+        match e {
+            Expression::Number(v) => Some(*v),
+            Expression::SymbolLiteral(x) => Some(self.get_symbol_value(x)),
+            Expression::TextLiteral(x) => Some(self.get_or_create_text_data(x)),
+            Expression::Identifier(x) => match self.resolve_identifier(&x.name) {
+                Some((_, IdentifierType::Local)) | None => None,
+                Some((idx, IdentifierType::Function)) => Some(self.get_handle(idx as u32)),
+                Some((v, _)) => Some(v),
+            },
+            _ => None,
+        }
+    }
+
+    /// compile an expression leaving a value of the given type on the stack
+    fn process_expression_as(&mut self, i: usize, e: &Expression, t: &DataType) -> Expr {
+        if let Some(c) = self.constant_value(e).and_then(|v| constant(t, v)) {
+            return c;
+        }
+        let (x, from) = self.process_expression(i, e);
+        if x.is_unreachable() {
+            return x;
+        }
+        convert(x, &from, t)
+    }
+
+    /// decide the type of every local of a function before compiling it, a
+    /// local that only ever holds integers that can't outgrow an i64 is kept
+    /// as one
+    fn plan_locals(&mut self, f: &FunctionDefinition) {
+        let assignments = assignments(&f.children);
+        let mut names: Vec<&str> = vec![];
+        for a in assignments.iter() {
+            if !f.params.contains(&a.id) && !names.contains(&a.id.as_str()) {
+                names.push(&a.id);
+            }
+        }
+        let mut plan: Vec<(Option<DataType>, bool)> = vec![(None, true); names.len()];
+        loop {
+            self.local_names = f.params.clone();
+            self.local_names.extend(names.iter().map(|n| n.to_string()));
+            self.local_types = f.param_types.clone();
+            self.local_types
+                .extend(plan.iter().map(|(t, _)| t.clone().unwrap_or(DataType::I64)));
+            self.local_inferred = vec![false; f.params.len()];
+            self.local_inferred
+                .extend(plan.iter().map(|(_, inferred)| *inferred));
+            let next = names
+                .iter()
+                .map(|name| {
+                    let values = assignments
+                        .iter()
+                        .filter(|a| a.id == *name)
+                        .map(|a| &*a.value)
+                        .collect::<Vec<&Expression>>();
+                    // a local bound to a declared type keeps it
+                    match self.expression_type(values[0]) {
+                        Some(t) if !self.is_inferred(values[0]) => (Some(t), false),
+                        _ => {
+                            let t = unify(values.iter().map(|v| self.expression_type(v)).collect());
+                            match t {
+                                Some(t)
+                                    if is_integer(&t) && values.iter().any(|v| self.grows(v)) =>
+                                {
+                                    (Some(DataType::F64), false)
+                                }
+                                t => {
+                                    let inferred = t.as_ref().map(is_integer).unwrap_or(true);
+                                    (t, inferred)
+                                }
+                            }
+                        }
+                    }
+                })
+                .collect::<Vec<(Option<DataType>, bool)>>();
+            if next == plan {
+                break;
+            }
+            plan = next;
+        }
+        self.local_plan = names
+            .iter()
+            .zip(plan)
+            .map(|(name, (t, inferred))| (name.to_string(), t.unwrap_or(DataType::I64), inferred))
+            .collect();
+        self.local_names = f.params.clone();
+        self.local_types = f.param_types.clone();
+        self.local_inferred = vec![false; f.params.len()];
+    }
+
+    /// compile expressions keeping only the value of the last one as the given type
+    fn process_block(&mut self, i: usize, e: &[Expression], t: Option<&DataType>) -> Vec<Expr> {
+        let mut block = vec![];
+        for (k, x) in e.iter().enumerate() {
+            match t {
+                Some(t) if k == e.len() - 1 => block.push(self.process_expression_as(i, x, t)),
+                _ => {
+                    let (x, _) = self.process_expression(i, x);
+                    block.push(discard(x));
+                }
+            }
+        }
+        block
+    }
+
+    /// compile an expression, returning it with the type of the value it leaves on the stack
+    fn process_expression(&mut self, i: usize, e: &Expression) -> (Expr, DataType) {
         match e {
             Expression::SymbolLiteral(x) => {
                 let v = self.get_symbol_value(x);
-                self.function_implementations[i].with_instructions(vec![F64_CONST, v.into()]);
+                (Expr::Const(Value::I32(v as i32)), DataType::I32)
             }
             Expression::FnSig(x) => {
-                let t = self
-                    .wasm
-                    .add_type(FunctionType::new(x.inputs.clone(), x.output.clone()));
-                self.function_implementations[i]
-                    .with_instructions(vec![F64_CONST, (t as f64).into()]);
+                let t = self.module.type_index(ir::Signature {
+                    params: x.inputs.clone(),
+                    output: x.output.clone(),
+                });
+                (Expr::Const(Value::I32(t as i32)), DataType::I32)
             }
             Expression::Loop(x) => {
+                let recur_depth = self.recur_depth;
+                let in_loop = self.in_loop;
                 self.recur_depth = 0;
-                if !x.expressions.is_empty() {
-                    self.function_implementations[i].with_instructions(vec![LOOP, F64]);
-                    for k in 0..x.expressions.len() {
-                        self.process_expression(i, &x.expressions[k]);
-                        if k != x.expressions.len() - 1 {
-                            self.function_implementations[i].with_instructions(vec![DROP]);
-                        }
-                    }
-                    self.function_implementations[i].with_instructions(vec![END]);
-                } else {
+                self.in_loop = true;
+                if x.expressions.is_empty() {
                     panic!("useless infinite loop detected")
                 }
+                let t = self.last_type(&x.expressions).unwrap_or(DataType::F64);
+                let body = self.process_block(i, &x.expressions, Some(&t));
+                self.recur_depth = recur_depth;
+                self.in_loop = in_loop;
+                (Expr::Loop(Some(t.clone()), body), t)
             }
+            Expression::Recur(_) if self.in_loop => (Expr::Br(self.recur_depth), DataType::F64),
+            // with no loop to go back to it returns from the function with 0
             Expression::Recur(_) => {
-                self.function_implementations[i].with_instructions(vec![
-                    F64_CONST,
-                    0.0.into(),
-                    BR,
-                    self.recur_depth.into(),
-                ]);
+                let zero = self
+                    .output
+                    .as_ref()
+                    .map(|t| Box::new(constant(t, 0.0).unwrap_or_else(|| panic!("no zero"))));
+                (Expr::Return(zero), DataType::F64)
             }
             Expression::IfStatement(x) => {
+                let t = self.branch_type(x);
+                let (condition, condition_type) = self.process_expression(i, &x.condition);
+                let condition = match condition_type {
+                    DataType::I32 => condition,
+                    t => Expr::Binary(
+                        BinaryOp::Ne,
+                        t.clone(),
+                        Box::new(condition),
+                        Box::new(constant(&t, 0.0).unwrap_or_else(|| panic!("no zero"))),
+                    ),
+                };
                 self.recur_depth += 1;
-                self.process_expression(i, &x.condition);
-                self.function_implementations[i].with_instructions(vec![
-                    F64_CONST,
-                    0.0.into(),
-                    F64_EQ,
-                    I32_CONST,
-                    0.into(),
-                    I32_EQ,
-                ]);
-                self.function_implementations[i].with_instructions(vec![IF, F64]);
-                for k in 0..x.if_true.len() {
-                    self.process_expression(i, &x.if_true[k]);
-                    if k != x.if_true.len() - 1 {
-                        self.function_implementations[i].with_instructions(vec![DROP]);
-                    }
-                }
-                self.function_implementations[i].with_instructions(vec![ELSE]);
-                if x.if_false.is_some() {
-                    for k in 0..x.if_false.as_ref().unwrap().len() {
-                        self.process_expression(i, &x.if_false.as_ref().unwrap()[k]);
-                        if k != x.if_false.as_ref().unwrap().len() - 1 {
-                            self.function_implementations[i].with_instructions(vec![DROP]);
-                        }
-                    }
-                } else {
-                    self.function_implementations[i].with_instructions(vec![F64_CONST, 0.0.into()]);
-                }
-                self.function_implementations[i].with_instructions(vec![END]);
+                let if_true = self.process_block(i, &x.if_true, Some(&t));
+                let if_false = match &x.if_false {
+                    Some(if_false) => self.process_block(i, if_false, Some(&t)),
+                    None => constant(&t, 0.0).into_iter().collect(),
+                };
+                self.recur_depth -= 1;
+                (
+                    Expr::If(Some(t.clone()), Box::new(condition), if_true, if_false),
+                    t,
+                )
             }
             Expression::Assignment(x) => {
-                self.process_expression(i, &x.value);
-                self.function_implementations[i].with_local(DataType::F64);
-                let p = self.resolve_identifier(&x.id);
-                let idx = if p.is_some() {
-                    let ident = p.unwrap();
-                    if ident.1 == IdentifierType::Local {
-                        ident.0 as u32
-                    } else {
-                        let l = self.local_names.len() as u32;
-                        self.local_names.push((&x.id).to_string());
-                        l
-                    }
-                } else {
-                    let l = self.local_names.len() as u32;
-                    self.local_names.push((&x.id).to_string());
-                    l
-                };
-                self.function_implementations[i].with_instructions(vec![
-                    LOCAL_SET,
-                    idx.into(),
-                    LOCAL_GET,
-                    idx.into(),
-                ]);
-            }
-            Expression::FunctionCall(x) => {
-                if &x.function_name == "assert" {
-                    if x.params.len() == 3 {
-                        self.process_expression(i, &x.params[0]);
-                        self.process_expression(i, &x.params[1]);
-                        self.function_implementations[i].with_instructions(vec![F64_EQ]);
-                        self.function_implementations[i].with_instructions(vec![IF, F64]);
-                        self.function_implementations[i]
-                            .with_instructions(vec![F64_CONST, 0.0.into()]);
-                        self.function_implementations[i].with_instructions(vec![ELSE]);
-                        self.process_expression(i, &x.params[2]);
-                        self.function_implementations[i].with_instructions(vec![
-                            BR,
-                            self.return_depth.into(),
-                            END,
-                        ]);
-                    } else {
-                        panic!("assert has 3 parameters")
-                    }
-                } else if &x.function_name == "call" {
-                    if x.params.len() >= 2 {
-                        if let Expression::FnSig(sig) = &x.params[0] {
-                            for k in 2..x.params.len() {
-                                self.process_expression(i, &x.params[k]);
-                            }
-                            self.process_expression(i, &x.params[1]);
-                            self.function_implementations[i]
-                                .with_instructions(vec![I32_TRUNC_S_F64]);
-                            let t = self.wasm.add_type(FunctionType::new(
-                                sig.inputs.clone(),
-                                sig.output.clone(),
-                            ));
-                            self.function_implementations[i].with_instructions(vec![
-                                CALL_INDIRECT,
-                                t.into(),
-                                0.into(),
-                            ]);
-                            if sig.output.is_none() {
-                                self.function_implementations[i]
-                                    .with_instructions(vec![F64_CONST, 0.0.into()]);
-                            }
-                        } else {
-                            panic!("call must begin with a function signature not an expression")
-                        }
-                    } else {
-                        panic!("call must have at least function signature and function index")
-                    }
-                } else if &x.function_name == "mem_byte" {
-                    if x.params.len() == 1 {
-                        self.process_expression(i, &x.params[0]);
-                        self.function_implementations[i].with_instructions(vec![I32_TRUNC_S_F64]);
-                        self.function_implementations[i].with_instructions(vec![
-                            I32_LOAD8_U,
-                            0.into(),
-                            0.into(),
-                            F64_CONVERT_S_I32,
-                        ]);
-                    } else if x.params.len() == 2 {
-                        for k in 0..x.params.len() {
-                            self.process_expression(i, &x.params[k]);
-                            self.function_implementations[i]
-                                .with_instructions(vec![I32_TRUNC_S_F64]);
-                        }
-                        self.function_implementations[i].with_instructions(vec![
-                            I32_STORE8,
-                            0.into(),
-                            0.into(),
-                        ]);
-                        self.function_implementations[i]
-                            .with_instructions(vec![F64_CONST, 0.0.into()]);
-                    } else {
-                        panic!("invalid number params for mem_byte")
+                let (idx, value) = match self.resolve_identifier(&x.id) {
+                    Some((idx, IdentifierType::Local)) => {
+                        // rebinding keeps the type of the local
+                        let idx = idx as usize;
+                        let t = self.local_types[idx].clone();
+                        (idx, self.process_expression_as(i, &x.value, &t))
                     }
-                } else if &x.function_name == "mem_heap_start" {
-                    if x.params.len() == 0 {
-                        self.function_implementations[i].with_instructions(vec![
-                            GLOBAL_GET,
-                            0.into(),
-                            F64_CONVERT_S_I32,
-                        ]);
-                    } else {
-                        panic!("invalid number params for mem_heap_start")
-                    }
-                } else if &x.function_name == "mem_heap_end" {
-                    if x.params.len() == 0 {
-                        self.function_implementations[i].with_instructions(vec![
-                            GLOBAL_GET,
-                            1.into(),
-                            F64_CONVERT_S_I32,
-                        ]);
-                    } else if x.params.len() == 1 {
-                        self.process_expression(i, &x.params[0]);
-                        self.function_implementations[i].with_instructions(vec![I32_TRUNC_S_F64]);
-                        self.function_implementations[i].with_instructions(vec![
-                            GLOBAL_SET,
-                            1.into(),
-                            I32_CONST,
-                            0.into(),
-                        ]);
-                    } else {
-                        panic!("invalid number params for mem_heap_start")
-                    }
-                } else if &x.function_name == "mem" {
-                    if x.params.len() == 1 {
-                        self.process_expression(i, &x.params[0]);
-                        self.function_implementations[i].with_instructions(vec![
-                            I32_TRUNC_S_F64,
-                            F64_LOAD,
-                            (0 as i32).into(),
-                            (0 as i32).into(),
-                        ]);
-                    } else if x.params.len() == 2 {
-                        self.process_expression(i, &x.params[0]);
-                        self.function_implementations[i].with_instructions(vec![I32_TRUNC_S_F64]);
-                        self.process_expression(i, &x.params[1]);
-                        self.function_implementations[i].with_instructions(vec![
-                            F64_STORE,
-                            (0 as i32).into(),
-                            (0 as i32).into(),
-                        ]);
-                        self.function_implementations[i]
-                            .with_instructions(vec![F64_CONST, 0.0.into()]);
-                    } else {
-                        panic!("invalid number params for mem")
-                    }
-                } else if &x.function_name == "=="
-                    || &x.function_name == "!="
-                    || &x.function_name == "<="
-                    || &x.function_name == ">="
-                    || &x.function_name == "<"
-                    || &x.function_name == ">"
-                {
-                    if x.params.len() != 2 {
-                        panic!(
-                            "operator {} expected 2 parameters",
-                            (&x.function_name).as_str()
-                        );
-                    }
-                    self.process_expression(i, &x.params[0]);
-                    self.process_expression(i, &x.params[1]);
-                    let mut f = match (&x.function_name).as_str() {
-                        "==" => vec![F64_EQ],
-                        "!=" => vec![F64_NE],
-                        "<=" => vec![F64_LE],
-                        ">=" => vec![F64_GE],
-                        "<" => vec![F64_LT],
-                        ">" => vec![F64_GT],
-                        _ => panic!("unexpected operator"),
-                    };
-                    f.extend(vec![F64_CONVERT_S_I32]);
-                    self.function_implementations[i].with_instructions(f);
-                } else if &x.function_name == "&"
-                    || &x.function_name == "|"
-                    || &x.function_name == "^"
-                    || &x.function_name == "<<"
-                    || &x.function_name == ">>"
-                {
-                    if x.params.len() != 2 {
-                        panic!(
-                            "operator {} expected 2 parameters",
-                            (&x.function_name).as_str()
-                        );
-                    }
-                    self.process_expression(i, &x.params[0]);
-                    self.function_implementations[i].with_instructions(vec![I64_TRUNC_S_F64]);
-                    self.process_expression(i, &x.params[1]);
-                    self.function_implementations[i].with_instructions(vec![I64_TRUNC_S_F64]);
-                    let mut f = match (&x.function_name).as_str() {
-                        "&" => vec![I64_AND],
-                        "|" => vec![I64_OR],
-                        "^" => vec![I64_XOR],
-                        "<<" => vec![I64_SHL],
-                        ">>" => vec![I64_SHR_S],
-                        _ => panic!("unexpected operator"),
-                    };
-                    f.extend(vec![F64_CONVERT_S_I64]);
-                    self.function_implementations[i].with_instructions(f);
-                } else if &x.function_name == "+"
-                    || &x.function_name == "-"
-                    || &x.function_name == "*"
-                    || &x.function_name == "/"
-                    || &x.function_name == "%"
-                {
-                    if x.params.len() < 2 {
-                        panic!(
-                            "operator {} expected at least 2 parameters",
-                            (&x.function_name).as_str()
-                        );
-                    }
-                    for p in 0..x.params.len() {
-                        self.process_expression(i, &x.params[p]);
-
-                        if &x.function_name == "%" {
-                            self.function_implementations[i]
-                                .with_instructions(vec![I64_TRUNC_S_F64]);
-                        }
-                        if p != 0 {
-                            let f = match (&x.function_name).as_str() {
-                                "+" => vec![F64_ADD],
-                                "-" => vec![F64_SUB],
-                                "*" => vec![F64_MUL],
-                                "/" => vec![F64_DIV],
-                                "%" => vec![I64_REM_S, F64_CONVERT_S_I64],
-                                _ => panic!("unexpected operator"),
-                            };
-                            self.function_implementations[i].with_instructions(f);
-                        }
-                    }
-                } else if &x.function_name == "!" {
-                    if x.params.len() != 1 {
-                        panic!(
-                            "operator {} expected 1 parameters",
-                            (&x.function_name).as_str()
-                        );
-                    }
-
-                    self.process_expression(i, &x.params[0]);
-                    self.function_implementations[i].with_instructions(vec![
-                        F64_CONST,
-                        0.0.into(),
-                        F64_EQ,
-                        F64_CONVERT_S_I32,
-                    ]);
-                } else if &x.function_name == "~" {
-                    if x.params.len() != 1 {
-                        panic!(
-                            "operator {} expected 1 parameters",
-                            (&x.function_name).as_str()
-                        );
-                    }
-
-                    self.process_expression(i, &x.params[0]);
-                    self.function_implementations[i].with_instructions(vec![
-                        I64_TRUNC_S_F64,
-                        I64_CONST,
-                        (-1 as i32).into(),
-                        I64_XOR,
-                        F64_CONVERT_S_I64,
-                    ]);
-                } else if &x.function_name == "and" {
-                    if x.params.len() != 2 {
-                        panic!(
-                            "operator {} expected 2 parameters",
-                            (&x.function_name).as_str()
-                        );
-                    }
-
-                    self.process_expression(i, &x.params[0]);
-                    self.function_implementations[i].with_instructions(vec![
-                        I64_TRUNC_S_F64,
-                        I64_CONST,
-                        0.into(),
-                        I64_NE,
-                    ]);
-                    self.process_expression(i, &x.params[1]);
-                    self.function_implementations[i].with_instructions(vec![
-                        I64_TRUNC_S_F64,
-                        I64_CONST,
-                        0.into(),
-                        I64_NE,
-                        I32_AND,
-                        F64_CONVERT_S_I32,
-                    ]);
-                } else if &x.function_name == "or" {
-                    if x.params.len() != 2 {
-                        panic!(
-                            "operator {} expected 2 parameters",
-                            (&x.function_name).as_str()
-                        );
-                    }
-
-                    self.process_expression(i, &x.params[0]);
-                    self.function_implementations[i].with_instructions(vec![I64_TRUNC_S_F64]);
-                    self.process_expression(i, &x.params[1]);
-                    self.function_implementations[i].with_instructions(vec![
-                        I64_TRUNC_S_F64,
-                        I64_OR,
-                        I64_CONST,
-                        0.into(),
-                        I64_NE,
-                        F64_CONVERT_S_I32,
-                    ]);
-                } else {
-                    let (function_handle, _) = self
-                        .resolve_identifier(&x.function_name)
-                        .expect(&format!("{} is not a valid function", &x.function_name));
-                    for k in 0..x.params.len() {
-                        self.process_expression(i, &x.params[k])
+                    _ => {
+                        let position = self
+                            .local_plan
+                            .iter()
+                            .position(|(name, _, _)| *name == x.id)
+                            .unwrap_or_else(|| panic!("{} was not planned", x.id));
+                        let (_, t, inferred) = self.local_plan.remove(position);
+                        let value = self.process_expression_as(i, &x.value, &t);
+                        self.functions[i].locals.push(t.clone());
+                        self.local_names.push(x.id.to_string());
+                        self.local_types.push(t);
+                        self.local_inferred.push(inferred);
+                        (self.local_names.len() - 1, value)
                     }
-                    self.function_implementations[i]
-                        .with_instructions(vec![CALL, (function_handle as i32).into()]);
-                }
+                };
+                (
+                    Expr::LocalTee(idx as u32, Box::new(value)),
+                    self.local_types[idx].clone(),
+                )
             }
+            Expression::FunctionCall(x) => self.process_call(i, x),
             Expression::TextLiteral(x) => {
-                let pos = self.get_or_create_text_data(&x);
-                self.function_implementations[i]
-                    .with_instructions(vec![F64_CONST, (pos as f64).into()]);
+                let pos = self.get_or_create_text_data(x);
+                (Expr::Const(Value::I32(pos as i32)), DataType::I32)
             }
             Expression::Identifier(x) => {
                 let val = self
-                    .resolve_identifier(&x)
-                    .expect(&format!("{} is not a valid identifier", &x));
+                    .resolve_identifier(&x.name)
+                    .unwrap_or_else(|| panic!("{} is not a valid identifier", &x.name));
                 match val.1 {
-                    IdentifierType::Global => {
-                        self.function_implementations[i]
-                            .with_instructions(vec![F64_CONST, val.0.into()]);
+                    IdentifierType::Local => (
+                        Expr::LocalGet(val.0 as u32),
+                        self.local_types[val.0 as usize].clone(),
+                    ),
+                    IdentifierType::Global | IdentifierType::Function => {
+                        let v = match val.1 {
+                            IdentifierType::Function => self.get_handle(val.0 as u32),
+                            _ => val.0,
+                        };
+                        let t = global_type(v);
+                        let c = constant(&t, v).unwrap_or_else(|| panic!("no constant"));
+                        (c, t)
+                    }
+                }
+            }
+            Expression::Number(x) => (Expr::Const(Value::F64(*x)), DataType::F64),
+        }
+    }
+
+    /// compile the operands of an operator as the given type
+    fn process_operands(&mut self, i: usize, x: &OperationFunctionCall, t: &DataType) -> Vec<Expr> {
+        x.params
+            .iter()
+            .map(|p| self.process_expression_as(i, p, t))
+            .collect()
+    }
+
+    #[allow(clippy::cognitive_complexity)]
+    fn process_call(&mut self, i: usize, x: &OperationFunctionCall) -> (Expr, DataType) {
+        match x.function_name.as_str() {
+            "assert" => {
+                if x.params.len() != 3 {
+                    panic!("assert has 3 parameters")
+                }
+                let expected = self.process_expression_as(i, &x.params[0], &DataType::F64);
+                let actual = self.process_expression_as(i, &x.params[1], &DataType::F64);
+                // a failed assert returns its message from the function
+                let fail = match self.output.clone() {
+                    Some(t) => vec![Expr::Return(Some(Box::new(self.process_expression_as(
+                        i,
+                        &x.params[2],
+                        &t,
+                    ))))],
+                    None => {
+                        let (message, _) = self.process_expression(i, &x.params[2]);
+                        vec![discard(message), Expr::Return(None)]
                     }
-                    IdentifierType::Local => {
-                        self.function_implementations[i]
-                            .with_instructions(vec![LOCAL_GET, (val.0 as i32).into()]);
+                };
+                (
+                    Expr::If(
+                        Some(DataType::F64),
+                        Box::new(Expr::Binary(
+                            BinaryOp::Eq,
+                            DataType::F64,
+                            Box::new(expected),
+                            Box::new(actual),
+                        )),
+                        vec![Expr::Const(Value::F64(0.0))],
+                        fail,
+                    ),
+                    DataType::F64,
+                )
+            }
+            "call" => {
+                if x.params.len() < 2 {
+                    panic!("call must have at least function signature and function index")
+                }
+                let sig = match &x.params[0] {
+                    Expression::FnSig(sig) => sig.clone(),
+                    _ => panic!("call must begin with a function signature not an expression"),
+                };
+                let mut args = vec![];
+                for (k, p) in x.params.iter().enumerate().skip(2) {
+                    let t = sig.inputs.get(k - 2).cloned().unwrap_or(DataType::F64);
+                    args.push(self.process_expression_as(i, p, &t));
+                }
+                let handle = self.process_expression_as(i, &x.params[1], &DataType::I32);
+                let t = self.module.type_index(ir::Signature {
+                    params: sig.inputs.clone(),
+                    output: sig.output.clone(),
+                });
+                let call = Expr::CallIndirect(t, Box::new(handle), args);
+                match sig.output {
+                    Some(t) => (call, t),
+                    None => (
+                        Expr::Sequence(vec![call, Expr::Const(Value::F64(0.0))]),
+                        DataType::F64,
+                    ),
+                }
+            }
+            "mem_byte" => {
+                let address = self.process_expression_as(i, &x.params[0], &DataType::I32);
+                match x.params.len() {
+                    1 => (Expr::Load(Access::Byte, Box::new(address)), DataType::I32),
+                    2 => {
+                        let value = self.process_expression_as(i, &x.params[1], &DataType::I32);
+                        (
+                            Expr::Sequence(vec![
+                                Expr::Store(Access::Byte, Box::new(address), Box::new(value)),
+                                Expr::Const(Value::I32(0)),
+                            ]),
+                            DataType::I32,
+                        )
                     }
-                    IdentifierType::Function => {
-                        self.function_implementations[i]
-                            .with_instructions(vec![F64_CONST, val.0.into()]);
+                    _ => panic!("invalid number params for mem_byte"),
+                }
+            }
+            "mem_heap_start" => {
+                if !x.params.is_empty() {
+                    panic!("invalid number params for mem_heap_start")
+                }
+                (Expr::GlobalGet(0), DataType::I32)
+            }
+            "mem_heap_end" => match x.params.len() {
+                0 => (Expr::GlobalGet(1), DataType::I32),
+                1 => {
+                    let value = self.process_expression_as(i, &x.params[0], &DataType::I32);
+                    (
+                        Expr::Sequence(vec![
+                            Expr::GlobalSet(1, Box::new(value)),
+                            Expr::Const(Value::I32(0)),
+                        ]),
+                        DataType::I32,
+                    )
+                }
+                _ => panic!("invalid number params for mem_heap_end"),
+            },
+            "mem" => {
+                let address = self.process_expression_as(i, &x.params[0], &DataType::I32);
+                match x.params.len() {
+                    1 => (Expr::Load(Access::F64, Box::new(address)), DataType::F64),
+                    2 => {
+                        let value = self.process_expression_as(i, &x.params[1], &DataType::F64);
+                        (
+                            Expr::Sequence(vec![
+                                Expr::Store(Access::F64, Box::new(address), Box::new(value)),
+                                Expr::Const(Value::I32(0)),
+                            ]),
+                            DataType::I32,
+                        )
                     }
+                    _ => panic!("invalid number params for mem"),
+                }
+            }
+            "==" | "!=" | "<=" | ">=" | "<" | ">" => {
+                if x.params.len() != 2 {
+                    panic!("operator {} expected 2 parameters", x.function_name);
+                }
+                let t = self.operand_type(&x.params).unwrap_or(DataType::F64);
+                let op = match x.function_name.as_str() {
+                    "==" => BinaryOp::Eq,
+                    "!=" => BinaryOp::Ne,
+                    "<=" => BinaryOp::Le,
+                    ">=" => BinaryOp::Ge,
+                    "<" => BinaryOp::Lt,
+                    _ => BinaryOp::Gt,
+                };
+                let mut operands = self.process_operands(i, x, &t);
+                let b = operands.pop().unwrap();
+                let a = operands.pop().unwrap();
+                (Expr::Binary(op, t, Box::new(a), Box::new(b)), DataType::I32)
+            }
+            "&" | "|" | "^" | "<<" | ">>" | "%" => {
+                if x.params.len() != 2 {
+                    panic!("operator {} expected 2 parameters", x.function_name);
+                }
+                let t = self.bitwise_type(&x.params).0;
+                let op = match x.function_name.as_str() {
+                    "&" => BinaryOp::And,
+                    "|" => BinaryOp::Or,
+                    "^" => BinaryOp::Xor,
+                    "<<" => BinaryOp::Shl,
+                    ">>" => BinaryOp::Shr,
+                    _ => BinaryOp::Rem,
+                };
+                let mut operands = self.process_operands(i, x, &t);
+                let b = operands.pop().unwrap();
+                let a = operands.pop().unwrap();
+                (Expr::Binary(op, t.clone(), Box::new(a), Box::new(b)), t)
+            }
+            "+" | "-" | "*" | "/" => {
+                if x.params.len() < 2 {
+                    panic!(
+                        "operator {} expected at least 2 parameters",
+                        x.function_name
+                    );
+                }
+                let t = self.arithmetic_type(x);
+                let op = match x.function_name.as_str() {
+                    "+" => BinaryOp::Add,
+                    "-" => BinaryOp::Sub,
+                    "*" => BinaryOp::Mul,
+                    _ => BinaryOp::Div,
+                };
+                let mut operands = self.process_operands(i, x, &t).into_iter();
+                let first = operands.next().unwrap();
+                let result = operands.fold(first, |a, b| {
+                    Expr::Binary(op, t.clone(), Box::new(a), Box::new(b))
+                });
+                (result, t)
+            }
+            "!" => {
+                if x.params.len() != 1 {
+                    panic!("operator {} expected 1 parameters", x.function_name);
                 }
+                let (value, t) = self.process_expression(i, &x.params[0]);
+                let test = match t {
+                    DataType::I32 | DataType::I64 => Expr::Eqz(t, Box::new(value)),
+                    t => Expr::Binary(
+                        BinaryOp::Eq,
+                        t.clone(),
+                        Box::new(value),
+                        Box::new(constant(&t, 0.0).unwrap_or_else(|| panic!("no zero"))),
+                    ),
+                };
+                (test, DataType::I32)
+            }
+            "~" => {
+                if x.params.len() != 1 {
+                    panic!("operator {} expected 1 parameters", x.function_name);
+                }
+                let t = self.bitwise_type(&x.params).0;
+                let value = self.process_expression_as(i, &x.params[0], &t);
+                let ones = constant(&t, -1.0).unwrap_or_else(|| panic!("no constant"));
+                (
+                    Expr::Binary(BinaryOp::Xor, t.clone(), Box::new(value), Box::new(ones)),
+                    t,
+                )
             }
-            Expression::Number(x) => {
-                self.function_implementations[i].with_instructions(vec![F64_CONST, (*x).into()]);
+            "and" | "or" => {
+                if x.params.len() != 2 {
+                    panic!("operator {} expected 2 parameters", x.function_name);
+                }
+                let mut operands = self.process_operands(i, x, &DataType::I64);
+                let b = operands.pop().unwrap();
+                let a = operands.pop().unwrap();
+                let is_true = |e: Expr| {
+                    Expr::Binary(
+                        BinaryOp::Ne,
+                        DataType::I64,
+                        Box::new(e),
+                        Box::new(Expr::Const(Value::I64(0))),
+                    )
+                };
+                let result = if x.function_name == "and" {
+                    Expr::Binary(
+                        BinaryOp::And,
+                        DataType::I32,
+                        Box::new(is_true(a)),
+                        Box::new(is_true(b)),
+                    )
+                } else {
+                    is_true(Expr::Binary(
+                        BinaryOp::Or,
+                        DataType::I64,
+                        Box::new(a),
+                        Box::new(b),
+                    ))
+                };
+                (result, DataType::I32)
+            }
+            name => {
+                let function_handle = match self.resolve_identifier(name) {
+                    Some((handle, IdentifierType::Function)) => handle as usize,
+                    _ => panic!("{} is not a valid function", name),
+                };
+                let (inputs, output) = self.function_types[function_handle].clone();
+                let args = x
+                    .params
+                    .iter()
+                    .zip(inputs.iter())
+                    .map(|(param, t)| self.process_expression_as(i, param, t))
+                    .collect();
+                let call = Expr::Call(function_handle as u32, args);
+                match output {
+                    Some(t) => (call, t),
+                    None => (
+                        Expr::Sequence(vec![call, Expr::Const(Value::F64(0.0))]),
+                        DataType::F64,
+                    ),
+                }
             }
         }
     }
@@ -656,45 +1046,538 @@ impl Compiler {
         // now lets process the insides of our functions
         for i in 0..self.function_defs.len() {
             if let TopLevelOperation::DefineFunction(f) = self.function_defs[i].clone() {
-                self.local_names = f.params.clone();
-                for j in 0..f.children.len() {
-                    self.process_expression(i, &f.children[j].clone());
-                    if j != f.children.len() - 1 {
-                        self.function_implementations[i].with_instructions(vec![DROP]);
-                    }
-                }
-                //end the function
-                self.function_implementations[i].with_instructions(vec![END]);
+                self.plan_locals(&f);
+                self.output = f.output.clone();
+                self.in_loop = false;
+                self.functions[i].body = self.process_block(i, &f.children, f.output.as_ref());
             }
         }
+    }
 
-        //now that we are done with everything, put funcions in the app
-        let num_funcs = self.function_defs.len();
-        for _ in 0..num_funcs {
-            let f = self.function_implementations.remove(0);
-            self.wasm.add_function(f);
-        }
+    /// the module compiled from the app
+    fn complete(mut self) -> ir::Module {
+        self.module.functions = self.functions;
+        self.module.data = self.layout.segments();
+        self.module.placements = self.layout.placements().to_vec();
+        self.module
+    }
+}
 
-        self.wasm.add_elements(
-            0,
-            self.function_names
-                .iter()
-                .enumerate()
-                .map(|(i, _)| Element::new(i as u32))
-                .collect::<Vec<Element>>(),
-        )
+/// web assembly proposals the generated module may use
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    /// `return_call` and `return_call_indirect`
+    TailCall,
+}
+
+impl Feature {
+    pub fn from_name(name: &str) -> Option<Feature> {
+        match name {
+            "tail-call" => Some(Feature::TailCall),
+            _ => None,
+        }
     }
+}
 
-    fn complete(&mut self) -> Vec<u8> {
-        self.wasm.to_bytes()
+/// how much effort goes into making the generated module smaller and faster
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub enum OptLevel {
+    /// compile everything as written
+    None,
+    /// remove what isn't used, work out constants, turn self recursive
+    /// tail calls into loops, share locals and clean up instructions
+    Basic,
+    /// also inline small functions
+    #[default]
+    Full,
+    /// everything, but only inline functions too small to make the module
+    /// bigger
+    Size,
+    /// also merge functions that compile the same and share identical data
+    MinSize,
+}
+
+impl OptLevel {
+    pub fn from_name(name: &str) -> Option<OptLevel> {
+        match name {
+            "0" => Some(OptLevel::None),
+            "1" => Some(OptLevel::Basic),
+            "2" => Some(OptLevel::Full),
+            "s" => Some(OptLevel::Size),
+            "z" => Some(OptLevel::MinSize),
+            _ => None,
+        }
     }
 }
 
+/// settings that affect the module being generated
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// initial size of linear memory in 64KiB pages
+    pub initial_memory: Option<u32>,
+    /// maximum size of linear memory in 64KiB pages
+    pub max_memory: Option<u32>,
+    /// have the host give linear memory as `env.memory`
+    pub import_memory: bool,
+    /// the name linear memory is exported as, `memory` if not given
+    pub export_memory_name: Option<String>,
+    /// let linear memory be used by several threads at once
+    pub shared_memory: bool,
+    /// project wide lint levels, applied in order
+    pub lints: Vec<(String, Level)>,
+    /// proposals beyond the web assembly MVP that can be used
+    pub features: Vec<Feature>,
+    pub opt_level: OptLevel,
+    /// leave out test functions and the names of functions
+    pub release: bool,
+}
+
+const DEFAULT_INITIAL_MEMORY: u32 = 2;
+const DEFAULT_MAX_MEMORY: u32 = 10;
+const MAX_MEMORY_PAGES: u32 = 65536;
+const PAGE_SIZE: u32 = 65536;
+
 pub fn compile(app: crate::ast::App) -> Result<Vec<u8>, Error> {
-    let mut compiler = Compiler::new(app);
+    compile_with_options(app, &Options::default()).map(|o| o.wasm)
+}
+
+/// the result of compiling an app
+#[derive(Debug)]
+pub struct Output {
+    pub wasm: Vec<u8>,
+    pub warnings: Vec<Warning>,
+    /// bytes of code the peephole optimizer removed
+    pub peephole_saved: usize,
+    /// what was encoded into the wasm
+    pub module: ir::Module,
+}
+
+/// test functions are exported functions whose names start with test_
+fn without_tests(app: crate::ast::App) -> crate::ast::App {
+    crate::ast::App {
+        children: app
+            .children
+            .into_iter()
+            .map(|op| match op {
+                TopLevelOperation::DefineFunction(mut f)
+                    if f.exported && unqualified_name(&f.name).starts_with("test_") =>
+                {
+                    f.exported = false;
+                    TopLevelOperation::DefineFunction(f)
+                }
+                op => op,
+            })
+            .collect(),
+    }
+}
+
+/// compile a checked app into a module and optimize it, calling functions
+/// in place of the ones they alias and inlining functions of up to
+/// `inline_size` expressions. Gives back the module and how many bytes the
+/// peephole optimizer saved.
+fn build(
+    app: &crate::ast::App,
+    options: &Options,
+    aliases: HashMap<String, String>,
+    inline_size: usize,
+) -> (ir::Module, usize) {
+    let optimize = options.opt_level >= OptLevel::Basic;
+    let mut compiler = Compiler::new(crate::ast::App {
+        children: app.children.clone(),
+    });
+    compiler.aliases = aliases;
+    compiler.share_data = options.opt_level >= OptLevel::MinSize;
     compiler.pre_process_functions();
     compiler.process_globals();
     compiler.process_functions();
     compiler.set_heap_start();
-    Ok(compiler.complete())
+    let mut module = compiler.complete();
+    if optimize {
+        crate::tail::eliminate_tail_calls(&mut module);
+    }
+    if options.opt_level >= OptLevel::Full {
+        let called_once = options.opt_level >= OptLevel::Size;
+        crate::inline::inline_functions(&mut module, inline_size, called_once);
+    }
+    if optimize {
+        crate::shake::remove_uncalled(&mut module);
+        crate::fold::fold_constants(&mut module);
+    }
+    if options.features.contains(&Feature::TailCall) {
+        crate::tail::emit_tail_calls(&mut module);
+    }
+    let mut peephole_saved = 0;
+    if optimize {
+        crate::locals::allocate_locals(&mut module);
+        peephole_saved = crate::peephole::optimize(&mut module);
+    }
+    (module, peephole_saved)
+}
+
+/// build an app, when optimizing for size trying both ways of inlining with
+/// and without merging functions and keeping whichever module is smallest
+fn build_smallest(app: &crate::ast::App, options: &Options) -> (ir::Module, usize) {
+    if options.opt_level < OptLevel::Size {
+        return build(app, options, HashMap::new(), crate::inline::INLINE_SIZE);
+    }
+    let mut smallest: Option<(ir::Module, usize, usize)> = None;
+    for inline_size in [crate::inline::SMALL_INLINE_SIZE, crate::inline::INLINE_SIZE] {
+        let built = build(app, options, HashMap::new(), inline_size);
+        let mut candidates = vec![];
+        if options.opt_level >= OptLevel::MinSize {
+            let aliases = crate::merge::identical_functions(&built.0);
+            if !aliases.is_empty() {
+                candidates.push(build(app, options, aliases, inline_size));
+            }
+        }
+        candidates.push(built);
+        for (module, saved) in candidates {
+            let size = crate::backend::encode(&module).len();
+            if smallest.as_ref().map(|s| size < s.2).unwrap_or(true) {
+                smallest = Some((module, saved, size));
+            }
+        }
+    }
+    let (module, saved, _) = smallest.expect("a module was built");
+    (module, saved)
+}
+
+pub fn compile_with_options(app: crate::ast::App, options: &Options) -> Result<Output, Error> {
+    let initial_memory = options.initial_memory.unwrap_or(DEFAULT_INITIAL_MEMORY);
+    let max_memory = options
+        .max_memory
+        .unwrap_or_else(|| std::cmp::max(DEFAULT_MAX_MEMORY, initial_memory));
+    if initial_memory > max_memory {
+        return Err(format_err!(
+            "initial memory of {} pages is larger than maximum memory of {} pages",
+            initial_memory,
+            max_memory
+        ));
+    }
+    if max_memory > MAX_MEMORY_PAGES {
+        return Err(format_err!(
+            "maximum memory can't be more than {} pages",
+            MAX_MEMORY_PAGES
+        ));
+    }
+    let app = crate::definitions::check_definitions(app)?;
+    crate::resolve::check_names(&app)?;
+    crate::arity::check_arity(&app)?;
+    crate::mutability::check_mutability(&app)?;
+    crate::signatures::check_signatures(&app)?;
+    let warnings = crate::lint::check_lints(&app, &options.lints)?;
+    let app = if options.release {
+        without_tests(app)
+    } else {
+        app
+    };
+    let app = if options.opt_level >= OptLevel::Basic {
+        crate::shake::remove_unreachable(app)
+    } else {
+        app
+    };
+    let (mut module, peephole_saved) = build_smallest(&app, options);
+    // the data is put in memory before anything can grow it
+    if u64::from(module.heap_start) > u64::from(initial_memory) * u64::from(PAGE_SIZE) {
+        return Err(format_err!(
+            "static data needs {} bytes but initial memory of {} pages is only {} bytes",
+            module.heap_start,
+            initial_memory,
+            u64::from(initial_memory) * u64::from(PAGE_SIZE)
+        ));
+    }
+    let memory_export = options
+        .export_memory_name
+        .clone()
+        .unwrap_or_else(|| "memory".to_string());
+    if let Some(f) = module
+        .functions
+        .iter()
+        .find(|f| f.export.as_ref() == Some(&memory_export))
+    {
+        return Err(format_err!(
+            "memory can't be exported as \"{}\", function {} is already exported with that name",
+            memory_export,
+            f.name
+        ));
+    }
+    module.names = !options.release;
+    module.initial_memory = initial_memory;
+    module.max_memory = max_memory;
+    module.import_memory = options.import_memory;
+    module.shared_memory = options.shared_memory;
+    module.memory_export = Some(memory_export);
+    Ok(Output {
+        wasm: crate::backend::encode(&module),
+        warnings,
+        peephole_saved,
+        module,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{compile_at, run, OPT_LEVELS};
+
+    /// a compiler ready to compile the first function of some source
+    fn compiler(source: &str) -> (Compiler, FunctionDefinition) {
+        let app = crate::parser::parse(source).expect("source parses");
+        let mut c = Compiler::new(app);
+        c.pre_process_functions();
+        c.process_globals();
+        let f = c
+            .function_defs
+            .iter()
+            .find_map(|d| match d {
+                TopLevelOperation::DefineFunction(f) => Some(f.clone()),
+                _ => None,
+            })
+            .expect("a function");
+        (c, f)
+    }
+
+    /// the type and inferredness planned for each local of the first function
+    fn planned(source: &str) -> Vec<(String, DataType, bool)> {
+        let (mut c, f) = compiler(source);
+        c.plan_locals(&f);
+        c.local_plan
+    }
+
+    fn local(name: &str, t: DataType, inferred: bool) -> (String, DataType, bool) {
+        (name.to_string(), t, inferred)
+    }
+
+    #[test]
+    fn counters_and_pointers_are_planned_as_integers() {
+        let plan = planned(
+            "pub fn f(n) {
+  mut i = 0
+  p = \"text\"
+  q = (p + 1)
+  loop { if (i < n) { i = (i + 1) recur } else { q } }
+}",
+        );
+        assert_eq!(
+            plan,
+            vec![
+                local("i", DataType::I64, true),
+                local("p", DataType::I32, true),
+                local("q", DataType::I64, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn locals_that_can_outgrow_an_integer_are_planned_as_f64() {
+        let plan = planned(
+            "pub fn f(n) {
+  mut x = 1
+  mut total = 0
+  mut twice = 1
+  mut i = 0
+  loop {
+    if (i < n) {
+      x = (x * 1000)
+      total = (total + i)
+      twice = (twice + twice)
+      i = (i + 1)
+      recur
+    } else { x }
+  }
+}",
+        );
+        assert_eq!(
+            plan,
+            vec![
+                local("x", DataType::F64, false),
+                local("total", DataType::F64, false),
+                local("twice", DataType::F64, false),
+                local("i", DataType::I64, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_types_are_kept_by_locals() {
+        let plan = planned("pub fn f(a: i32) { b = (a * a) c = (b + 1.5) c }");
+        assert_eq!(
+            plan,
+            vec![
+                local("b", DataType::I32, false),
+                local("c", DataType::F64, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn arithmetic_on_inferred_integers_only_adds_and_subtracts_as_integers() {
+        let (mut c, f) = compiler(
+            "pub fn f(a: i32, b: i32, x) {
+  (a + b) (a * b) (\"s\" + 1) (\"s\" - size_num) (\"s\" * 2) (\"s\" / 2) (x + 1)
+}",
+        );
+        c.plan_locals(&f);
+        let types = f
+            .children
+            .iter()
+            .map(|e| match e {
+                Expression::FunctionCall(x) => c.arithmetic_type(x),
+                _ => panic!("an operator"),
+            })
+            .collect::<Vec<DataType>>();
+        assert_eq!(
+            types,
+            vec![
+                DataType::I32,
+                DataType::I32,
+                DataType::I64,
+                DataType::I64,
+                DataType::F64,
+                DataType::F64,
+                DataType::F64,
+            ]
+        );
+    }
+
+    #[test]
+    fn whole_numbers_multiplied_past_an_integer_keep_growing_as_f64() {
+        let source = "pub fn powers() {
+  mut x = 1
+  mut i = 0
+  loop { if (i < 7) { x = (x * 1000) i = (i + 1) recur } else { x } }
+}
+pub fn hash(s) {
+  mut h = 0
+  mut p = s
+  loop {
+    c = mem_byte(p)
+    if (c == 0) { h } else { h = ((h * 31) + c) p = (p + 1) recur }
+  }
+}
+pub fn doubled(n) {
+  mut x = 1
+  mut i = 0
+  loop { if (i < n) { x = (x + x) i = (i + 1) recur } else { x } }
+}
+pub fn text() { \"the quick brown fox jumps over the lazy dog\" }";
+        let mut expected_hash = 0.0;
+        for c in "the quick brown fox jumps over the lazy dog".bytes() {
+            expected_hash = expected_hash * 31.0 + f64::from(c);
+        }
+        for level in OPT_LEVELS {
+            let wasm = compile_at(source, *level);
+            assert_eq!(run(&wasm, "powers", &[]).0, 1e21, "{:?}", level);
+            let text = run(&wasm, "text", &[]).0;
+            assert_eq!(run(&wasm, "hash", &[text]).0, expected_hash, "{:?}", level);
+            assert_eq!(
+                run(&wasm, "doubled", &[70.0]).0,
+                2f64.powi(70),
+                "{:?}",
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn recur_outside_a_loop_returns_zero() {
+        let source = "pub fn f(x) { if x { recur } else { 1 } }
+pub fn g(x: i32) -> i32 { if x { recur } else { 2 } }";
+        for level in OPT_LEVELS {
+            let wasm = compile_at(source, *level);
+            assert_eq!(run(&wasm, "f", &[1.0]).0, 0.0, "{:?}", level);
+            assert_eq!(run(&wasm, "f", &[0.0]).0, 1.0, "{:?}", level);
+            assert_eq!(run(&wasm, "g", &[1.0]).0, 0.0, "{:?}", level);
+            assert_eq!(run(&wasm, "g", &[0.0]).0, 2.0, "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn optimizing_for_size_is_never_larger_than_full_optimization() {
+        // helpers called from one place each, too big for the size budget
+        let source = "extern console_log(x)
+static points = (1, 2, 3, 4, 5, 6, 7, 8)
+fn point_x(i) { mem((points + (i * size_num))) }
+fn scale(v, k) { if (v > 4) { (v * k) } else { (v + k) } }
+fn report(a, b, c) { console_log(a) console_log(b) console_log(c) ((a + b) + c) }
+fn step(i, acc) { x = point_x(i) y = scale(x, 3) z = scale(y, 0.5) (acc + report(x, y, z)) }
+fn run_all(n) {
+  mut i = 0
+  mut acc = 0
+  loop { if (i < n) { acc = step(i, acc) i = (i + 1) recur } else { acc } }
+}
+pub fn main() { run_all(8) }";
+        let full = compile_at(source, OptLevel::Full);
+        for level in [OptLevel::Size, OptLevel::MinSize] {
+            let small = compile_at(source, level);
+            assert!(
+                small.len() <= full.len(),
+                "{:?} is {} bytes, -O2 is {}",
+                level,
+                small.len(),
+                full.len()
+            );
+            assert_eq!(run(&small, "main", &[]).0, run(&full, "main", &[]).0);
+        }
+    }
+
+    #[test]
+    fn memory_options_are_checked() {
+        let error = |source: &str, options: Options| {
+            let app = crate::parser::parse(source).expect("source parses");
+            compile_with_options(app, &options).unwrap_err().to_string()
+        };
+        let source = "pub fn main() { 1 }";
+        assert_eq!(
+            error(
+                source,
+                Options {
+                    initial_memory: Some(4),
+                    max_memory: Some(3),
+                    ..Default::default()
+                }
+            ),
+            "initial memory of 4 pages is larger than maximum memory of 3 pages"
+        );
+        assert_eq!(
+            error(
+                source,
+                Options {
+                    max_memory: Some(MAX_MEMORY_PAGES + 1),
+                    ..Default::default()
+                }
+            ),
+            "maximum memory can't be more than 65536 pages"
+        );
+        assert_eq!(
+            error(
+                source,
+                Options {
+                    export_memory_name: Some("main".to_string()),
+                    ..Default::default()
+                }
+            ),
+            "memory can't be exported as \"main\", function main is already exported with that name"
+        );
+        let big = format!(
+            "static big = \"{}\"\npub fn main() {{ big }}",
+            "x".repeat(70000)
+        );
+        assert!(error(
+            &big,
+            Options {
+                initial_memory: Some(1),
+                ..Default::default()
+            }
+        )
+        .ends_with("but initial memory of 1 pages is only 65536 bytes"));
+        // a larger initial memory raises the default maximum with it
+        let app = crate::parser::parse(source).expect("source parses");
+        let options = Options {
+            initial_memory: Some(20),
+            ..Default::default()
+        };
+        let module = compile_with_options(app, &options).unwrap().module;
+        assert_eq!((module.initial_memory, module.max_memory), (20, 20));
+    }
 }
@@ -0,0 +1,235 @@
+use crate::ast::*;
+use crate::modules::unqualified_name;
+use failure::Error;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Function,
+    Extern,
+    Static,
+    Struct,
+}
+
+impl Kind {
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::Function => "function",
+            Kind::Extern => "extern",
+            Kind::Static => "static",
+            Kind::Struct => "struct",
+        }
+    }
+}
+
+struct Definition {
+    index: usize,
+    kind: Kind,
+    overrides: bool,
+    location: Location,
+}
+
+fn definition(index: usize, op: &TopLevelOperation) -> Option<(&str, Definition)> {
+    match op {
+        TopLevelOperation::DefineFunction(f) => Some((
+            &f.name,
+            Definition {
+                index,
+                kind: Kind::Function,
+                overrides: f.overrides,
+                location: f.location.clone(),
+            },
+        )),
+        TopLevelOperation::ExternalFunction(f) => Some((
+            &f.name,
+            Definition {
+                index,
+                kind: Kind::Extern,
+                overrides: false,
+                location: f.location.clone(),
+            },
+        )),
+        TopLevelOperation::DefineGlobal(g) => Some((
+            &g.name,
+            Definition {
+                index,
+                kind: match g.value {
+                    GlobalValue::Struct(_) => Kind::Struct,
+                    _ => Kind::Static,
+                },
+                overrides: g.overrides,
+                location: g.location.clone(),
+            },
+        )),
+        _ => None,
+    }
+}
+
+/// make sure every name is defined once, applying `override` definitions
+/// by removing the definition they replace
+pub fn check_definitions(app: App) -> Result<App, Error> {
+    let mut names: Vec<&str> = vec![];
+    let mut definitions: HashMap<&str, Vec<Definition>> = HashMap::new();
+    for (i, op) in app.children.iter().enumerate() {
+        if let Some((name, d)) = definition(i, op) {
+            if !definitions.contains_key(name) {
+                names.push(name);
+            }
+            definitions.entry(name).or_default().push(d);
+        }
+    }
+
+    let mut errors = vec![];
+    let mut removed = vec![];
+    for name in names.iter() {
+        let defs = &definitions[name];
+        let (overrides, originals): (Vec<&Definition>, Vec<&Definition>) =
+            defs.iter().partition(|d| d.overrides);
+        for d in originals.iter().skip(1) {
+            let first = originals[0];
+            let conflict = if first.kind == d.kind {
+                format!("{} {} is defined more than once", d.kind.describe(), name)
+            } else {
+                format!(
+                    "{} {} conflicts with {} of the same name",
+                    d.kind.describe(),
+                    name,
+                    first.kind.describe()
+                )
+            };
+            errors.push(format!(
+                "{}: {}\n  {}: first defined here",
+                d.location, conflict, first.location
+            ));
+        }
+        for d in overrides.iter().skip(1) {
+            errors.push(format!(
+                "{}: {} is overridden more than once\n  {}: first overridden here",
+                d.location, name, overrides[0].location
+            ));
+        }
+        if let Some(o) = overrides.first() {
+            match originals.first() {
+                None => errors.push(format!(
+                    "{}: override of {} doesn't replace any definition",
+                    o.location, name
+                )),
+                Some(original) if original.kind != o.kind => errors.push(format!(
+                    "{}: {} {} can't override {} of the same name\n  {}: overridden definition",
+                    o.location,
+                    o.kind.describe(),
+                    name,
+                    original.kind.describe(),
+                    original.location
+                )),
+                Some(_) => removed.extend(originals.iter().map(|d| d.index)),
+            }
+        }
+    }
+
+    // names the host sees must be unique too
+    let mut exports: HashMap<&str, &Location> = HashMap::new();
+    for (i, op) in app.children.iter().enumerate() {
+        if let TopLevelOperation::DefineFunction(f) = op {
+            if !f.exported || removed.contains(&i) {
+                continue;
+            }
+            let export = unqualified_name(&f.name);
+            match exports.get(export) {
+                Some(first) => errors.push(format!(
+                    "{}: function {} is exported more than once\n  {}: first exported here",
+                    f.location, export, first
+                )),
+                None => {
+                    exports.insert(export, &f.location);
+                }
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(format_err!("{}", errors.join("\n")));
+    }
+    Ok(App {
+        children: app
+            .children
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !removed.contains(i))
+            .map(|(_, op)| op)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: &str) -> Result<Vec<String>, String> {
+        let app = crate::parser::parse(source).expect("source parses");
+        check_definitions(app)
+            .map(|app| {
+                app.children
+                    .iter()
+                    .filter_map(|op| definition(0, op).map(|(_, d)| d.location.to_string()))
+                    .collect()
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    fn module(file: &str, source: &str, entry: bool) -> crate::modules::Module {
+        crate::modules::Module {
+            path: vec!["p".to_string(), file.trim_end_matches(".w").to_string()],
+            root: true,
+            entry,
+            app: crate::parser::parse_file(file, source).expect("source parses"),
+        }
+    }
+
+    #[test]
+    fn names_are_defined_once() {
+        assert_eq!(
+            check("fn f() { 1 }\nfn f() { 2 }").unwrap_err(),
+            "2:1: function f is defined more than once\n  1:1: first defined here"
+        );
+        assert_eq!(
+            check("static f = 1\nextern f()").unwrap_err(),
+            "2:1: extern f conflicts with static of the same name\n  1:1: first defined here"
+        );
+    }
+
+    #[test]
+    fn overrides_replace_the_definition_of_the_same_name() {
+        assert_eq!(
+            check("fn f() { 1 }\nfn g() { 2 }\noverride fn f() { 3 }").unwrap(),
+            vec!["2:1", "3:1"]
+        );
+        let source = "fn f() { 1 }\noverride fn f() { 2 }\noverride fn f() { 3 }";
+        assert_eq!(
+            check(source).unwrap_err(),
+            "3:1: f is overridden more than once\n  2:1: first overridden here"
+        );
+        assert_eq!(
+            check("override fn f() { 1 }").unwrap_err(),
+            "1:1: override of f doesn't replace any definition"
+        );
+        assert_eq!(
+            check("static f = 1\noverride fn f() { 2 }").unwrap_err(),
+            "2:1: function f can't override static of the same name\n  1:1: overridden definition"
+        );
+    }
+
+    #[test]
+    fn overridden_exports_are_not_exported_twice() {
+        assert!(check("pub fn f() { 1 }\npub override fn f() { 2 }").is_ok());
+        let app = crate::modules::link(vec![
+            module("main.w", "pub fn f() { 1 }", true),
+            module("lib.w", "pub fn f() { 2 }", true),
+        ])
+        .unwrap();
+        assert_eq!(
+            check_definitions(app).unwrap_err().to_string(),
+            "lib.w:1:1: function f is exported more than once\n  main.w:1:1: first exported here"
+        );
+    }
+}
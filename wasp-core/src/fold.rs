@@ -0,0 +1,443 @@
+use crate::ir::*;
+use wasmly::DataType;
+
+fn truth(b: bool) -> Value {
+    Value::I32(b as i32)
+}
+
+/// the result of an operator on constants, None when it would trap or
+/// doesn't exist for the type
+fn binary(op: BinaryOp, a: Value, b: Value) -> Option<Value> {
+    use BinaryOp::*;
+    Some(match (a, b) {
+        (Value::I32(a), Value::I32(b)) => match op {
+            Add => Value::I32(a.wrapping_add(b)),
+            Sub => Value::I32(a.wrapping_sub(b)),
+            Mul => Value::I32(a.wrapping_mul(b)),
+            Div => Value::I32(a.checked_div(b)?),
+            Rem if b == 0 => return None,
+            Rem => Value::I32(a.wrapping_rem(b)),
+            And => Value::I32(a & b),
+            Or => Value::I32(a | b),
+            Xor => Value::I32(a ^ b),
+            Shl => Value::I32(a.wrapping_shl(b as u32)),
+            Shr => Value::I32(a.wrapping_shr(b as u32)),
+            Eq => truth(a == b),
+            Ne => truth(a != b),
+            Lt => truth(a < b),
+            Gt => truth(a > b),
+            Le => truth(a <= b),
+            Ge => truth(a >= b),
+        },
+        (Value::I64(a), Value::I64(b)) => match op {
+            Add => Value::I64(a.wrapping_add(b)),
+            Sub => Value::I64(a.wrapping_sub(b)),
+            Mul => Value::I64(a.wrapping_mul(b)),
+            Div => Value::I64(a.checked_div(b)?),
+            Rem if b == 0 => return None,
+            Rem => Value::I64(a.wrapping_rem(b)),
+            And => Value::I64(a & b),
+            Or => Value::I64(a | b),
+            Xor => Value::I64(a ^ b),
+            Shl => Value::I64(a.wrapping_shl(b as u32)),
+            Shr => Value::I64(a.wrapping_shr(b as u32)),
+            Eq => truth(a == b),
+            Ne => truth(a != b),
+            Lt => truth(a < b),
+            Gt => truth(a > b),
+            Le => truth(a <= b),
+            Ge => truth(a >= b),
+        },
+        (Value::F32(a), Value::F32(b)) => match op {
+            Add => Value::F32(a + b),
+            Sub => Value::F32(a - b),
+            Mul => Value::F32(a * b),
+            Div => Value::F32(a / b),
+            Eq => truth(a == b),
+            Ne => truth(a != b),
+            Lt => truth(a < b),
+            Gt => truth(a > b),
+            Le => truth(a <= b),
+            Ge => truth(a >= b),
+            _ => return None,
+        },
+        (Value::F64(a), Value::F64(b)) => match op {
+            Add => Value::F64(a + b),
+            Sub => Value::F64(a - b),
+            Mul => Value::F64(a * b),
+            Div => Value::F64(a / b),
+            Eq => truth(a == b),
+            Ne => truth(a != b),
+            Lt => truth(a < b),
+            Gt => truth(a > b),
+            Le => truth(a <= b),
+            Ge => truth(a >= b),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+/// a float truncated towards zero, None if it doesn't fit between the bounds
+fn truncate(v: f64, min: f64, max: f64) -> Option<f64> {
+    let t = v.trunc();
+    if t >= min && t < max {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// a constant converted to another type, None when it would trap
+fn convert(v: Value, to: &DataType) -> Option<Value> {
+    const I32_BOUNDS: (f64, f64) = (-2_147_483_648.0, 2_147_483_648.0);
+    const I64_BOUNDS: (f64, f64) = (-9_223_372_036_854_775_808.0, 9_223_372_036_854_775_808.0);
+    Some(match (v, to) {
+        (Value::I32(v), DataType::I64) => Value::I64(i64::from(v)),
+        (Value::I32(v), DataType::F32) => Value::F32(v as f32),
+        (Value::I32(v), DataType::F64) => Value::F64(f64::from(v)),
+        (Value::I64(v), DataType::I32) => Value::I32(v as i32),
+        (Value::I64(v), DataType::F32) => Value::F32(v as f32),
+        (Value::I64(v), DataType::F64) => Value::F64(v as f64),
+        (Value::F32(v), DataType::I32) => {
+            Value::I32(truncate(f64::from(v), I32_BOUNDS.0, I32_BOUNDS.1)? as i32)
+        }
+        (Value::F32(v), DataType::I64) => {
+            Value::I64(truncate(f64::from(v), I64_BOUNDS.0, I64_BOUNDS.1)? as i64)
+        }
+        (Value::F32(v), DataType::F64) => Value::F64(f64::from(v)),
+        (Value::F64(v), DataType::I32) => {
+            Value::I32(truncate(v, I32_BOUNDS.0, I32_BOUNDS.1)? as i32)
+        }
+        (Value::F64(v), DataType::I64) => {
+            Value::I64(truncate(v, I64_BOUNDS.0, I64_BOUNDS.1)? as i64)
+        }
+        (Value::F64(v), DataType::F32) => Value::F32(v as f32),
+        _ => return None,
+    })
+}
+
+fn is_zero(v: Value) -> bool {
+    match v {
+        Value::I32(v) => v == 0,
+        Value::I64(v) => v == 0,
+        Value::F32(v) => v == 0.0,
+        Value::F64(v) => v == 0.0,
+    }
+}
+
+/// expressions that can be removed when their value isn't used
+pub(crate) fn is_pure(e: &Expr) -> bool {
+    matches!(e, Expr::Const(_) | Expr::LocalGet(_) | Expr::GlobalGet(_))
+}
+
+/// does a branch in a list of expressions target the label `depth` levels out
+fn targets(e: &[Expr], depth: u32) -> bool {
+    let mut found = false;
+    for x in e.iter() {
+        walk(x, depth, &mut |x, depth| {
+            if let Expr::Br(d) = x {
+                found |= *d == depth;
+            }
+        });
+    }
+    found
+}
+
+/// branches out of a label that is being removed now have one less label to cross
+fn retarget(e: &mut [Expr], depth: u32) {
+    for x in e.iter_mut() {
+        walk_mut(x, depth, &mut |x, depth| {
+            if let Expr::Br(d) = x {
+                if *d > depth {
+                    *d -= 1;
+                }
+            }
+        });
+    }
+}
+
+fn fold_boxed(mut e: Box<Expr>) -> Box<Expr> {
+    *e = fold_expression(*e);
+    e
+}
+
+fn fold_all(e: Vec<Expr>) -> Vec<Expr> {
+    e.into_iter().map(fold_expression).collect()
+}
+
+/// fold a list of expressions, ones whose values are thrown away are only
+/// kept for their effects
+fn fold_block(e: Vec<Expr>) -> Vec<Expr> {
+    let mut block = vec![];
+    for x in e.into_iter().map(fold_expression) {
+        match x {
+            Expr::Drop(v) if is_pure(&v) => {}
+            Expr::Drop(v) => match *v {
+                Expr::Sequence(mut s) if s.last().map(is_pure).unwrap_or(false) => {
+                    s.pop();
+                    block.extend(s);
+                }
+                v => block.push(Expr::Drop(Box::new(v))),
+            },
+            x => block.push(x),
+        }
+    }
+    block
+}
+
+/// fold an expression as far as its constants allow
+pub fn fold_expression(e: Expr) -> Expr {
+    match e {
+        Expr::Binary(op, t, a, b) => {
+            let (a, b) = (fold_expression(*a), fold_expression(*b));
+            if let (Expr::Const(x), Expr::Const(y)) = (&a, &b) {
+                if let Some(v) = binary(op, *x, *y) {
+                    return Expr::Const(v);
+                }
+            }
+            Expr::Binary(op, t, Box::new(a), Box::new(b))
+        }
+        Expr::Convert(from, to, v) => {
+            let v = fold_expression(*v);
+            if let Expr::Const(x) = &v {
+                if let Some(c) = convert(*x, &to) {
+                    return Expr::Const(c);
+                }
+            }
+            Expr::Convert(from, to, Box::new(v))
+        }
+        Expr::Eqz(t, v) => {
+            let v = fold_expression(*v);
+            if let Expr::Const(x) = &v {
+                return Expr::Const(truth(is_zero(*x)));
+            }
+            Expr::Eqz(t, Box::new(v))
+        }
+        Expr::If(t, condition, if_true, if_false) => {
+            let condition = fold_expression(*condition);
+            let if_true = fold_block(if_true);
+            let if_false = fold_block(if_false);
+            match condition {
+                Expr::Const(c) => {
+                    let mut taken = if is_zero(c) { if_false } else { if_true };
+                    if targets(&taken, 0) {
+                        Expr::Block(t, taken)
+                    } else {
+                        retarget(&mut taken, 0);
+                        match taken.len() {
+                            1 => taken.pop().unwrap(),
+                            _ => Expr::Sequence(taken),
+                        }
+                    }
+                }
+                condition => Expr::If(t, Box::new(condition), if_true, if_false),
+            }
+        }
+        Expr::LocalSet(i, v) => Expr::LocalSet(i, fold_boxed(v)),
+        Expr::LocalTee(i, v) => Expr::LocalTee(i, fold_boxed(v)),
+        Expr::GlobalSet(i, v) => Expr::GlobalSet(i, fold_boxed(v)),
+        Expr::Load(access, v) => Expr::Load(access, fold_boxed(v)),
+        Expr::Store(access, a, v) => Expr::Store(access, fold_boxed(a), fold_boxed(v)),
+        Expr::Call(f, args) => Expr::Call(f, fold_all(args)),
+        Expr::CallIndirect(t, callee, args) => {
+            Expr::CallIndirect(t, fold_boxed(callee), fold_all(args))
+        }
+        Expr::Sequence(e) => Expr::Sequence(fold_block(e)),
+        Expr::Block(t, e) => Expr::Block(t, fold_block(e)),
+        Expr::Loop(t, e) => Expr::Loop(t, fold_block(e)),
+        Expr::Return(v) => Expr::Return(v.map(fold_boxed)),
+        Expr::TailCall(v) => Expr::TailCall(fold_boxed(v)),
+        Expr::Drop(v) => Expr::Drop(fold_boxed(v)),
+        e => e,
+    }
+}
+
+/// the value of locals that are set exactly once to a constant, where that
+/// set always runs before the local is read. Wasp only reads a local after
+/// it is assigned, so that holds for sets not inside an if or block.
+fn constant_locals(f: &Function) -> Vec<(u32, Value)> {
+    let params = f.signature.params.len() as u32;
+    let mut sets: Vec<(u32, Option<Value>)> = vec![];
+    fn collect(e: &[Expr], conditional: bool, sets: &mut Vec<(u32, Option<Value>)>) {
+        for x in e.iter() {
+            collect_expression(x, conditional, sets);
+        }
+    }
+    fn collect_expression(e: &Expr, conditional: bool, sets: &mut Vec<(u32, Option<Value>)>) {
+        match e {
+            Expr::LocalSet(i, v) | Expr::LocalTee(i, v) => {
+                let value = match **v {
+                    Expr::Const(c) if !conditional => Some(c),
+                    _ => None,
+                };
+                sets.push((*i, value));
+                collect_expression(v, conditional, sets);
+            }
+            Expr::GlobalSet(_, v)
+            | Expr::Load(_, v)
+            | Expr::Eqz(_, v)
+            | Expr::Convert(_, _, v)
+            | Expr::Drop(v)
+            | Expr::Return(Some(v))
+            | Expr::TailCall(v) => collect_expression(v, conditional, sets),
+            Expr::Store(_, a, b) | Expr::Binary(_, _, a, b) => {
+                collect_expression(a, conditional, sets);
+                collect_expression(b, conditional, sets);
+            }
+            Expr::Call(_, args) | Expr::Sequence(args) => collect(args, conditional, sets),
+            Expr::CallIndirect(_, callee, args) => {
+                collect(args, conditional, sets);
+                collect_expression(callee, conditional, sets);
+            }
+            Expr::Loop(_, e) => collect(e, conditional, sets),
+            Expr::Block(_, e) => collect(e, true, sets),
+            Expr::If(_, condition, if_true, if_false) => {
+                collect_expression(condition, conditional, sets);
+                collect(if_true, true, sets);
+                collect(if_false, true, sets);
+            }
+            _ => {}
+        }
+    }
+    collect(&f.body, false, &mut sets);
+
+    let mut constants = vec![];
+    for (i, value) in sets.iter() {
+        if *i < params || sets.iter().filter(|(j, _)| j == i).count() != 1 {
+            continue;
+        }
+        if let Some(v) = value {
+            constants.push((*i, *v));
+        }
+    }
+    constants
+}
+
+/// replace reads of constant locals with their value, the locals are no longer set
+fn propagate(e: &mut [Expr], constants: &[(u32, Value)]) {
+    let value = |i: u32| constants.iter().find(|(j, _)| *j == i).map(|(_, v)| *v);
+    for x in e.iter_mut() {
+        walk_mut(x, 0, &mut |x, _| {
+            let replacement = match x {
+                Expr::LocalGet(i) => value(*i).map(Expr::Const),
+                Expr::LocalTee(i, v) if value(*i).is_some() => Some((**v).clone()),
+                Expr::LocalSet(i, v) if value(*i).is_some() => Some(Expr::Drop(v.clone())),
+                _ => None,
+            };
+            if let Some(r) = replacement {
+                *x = r;
+            }
+        });
+    }
+}
+
+/// evaluate what can be known while compiling: operators on constants,
+/// ifs with constant conditions and locals that only ever hold one constant
+pub fn fold_constants(module: &mut Module) {
+    for f in module.functions.iter_mut() {
+        loop {
+            f.body = fold_block(std::mem::take(&mut f.body));
+            let constants = constant_locals(f);
+            if constants.is_empty() {
+                break;
+            }
+            propagate(&mut f.body, &constants);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: i32) -> Expr {
+        Expr::Const(Value::I32(n))
+    }
+
+    fn binary(op: BinaryOp, a: Expr, b: Expr) -> Expr {
+        Expr::Binary(op, DataType::I32, Box::new(a), Box::new(b))
+    }
+
+    #[test]
+    fn operators_on_constants_are_evaluated() {
+        let e = binary(BinaryOp::Mul, binary(BinaryOp::Add, int(2), int(3)), int(4));
+        assert_eq!(fold_expression(e), int(20));
+        let e = Expr::Eqz(
+            DataType::I32,
+            Box::new(binary(BinaryOp::Lt, int(1), int(2))),
+        );
+        assert_eq!(fold_expression(e), int(0));
+        // dividing by zero traps, so it is left for when the module runs
+        let e = binary(BinaryOp::Div, int(1), int(0));
+        assert_eq!(fold_expression(e.clone()), e);
+    }
+
+    #[test]
+    fn ifs_with_constant_conditions_keep_only_the_branch_taken() {
+        let e = Expr::If(
+            Some(DataType::I32),
+            Box::new(binary(BinaryOp::Eq, int(1), int(1))),
+            vec![int(2)],
+            vec![int(3)],
+        );
+        assert_eq!(fold_expression(e), int(2));
+        // a branch out past the if now has one less label to cross
+        let e = Expr::If(
+            None,
+            Box::new(int(0)),
+            vec![],
+            vec![Expr::Br(1), Expr::Br(2)],
+        );
+        assert_eq!(
+            fold_expression(e),
+            Expr::Sequence(vec![Expr::Br(0), Expr::Br(1)])
+        );
+        // a branch to the end of the if needs a block to go to instead
+        let e = Expr::If(None, Box::new(int(1)), vec![Expr::Br(0)], vec![]);
+        assert_eq!(fold_expression(e), Expr::Block(None, vec![Expr::Br(0)]));
+    }
+
+    #[test]
+    fn locals_set_once_to_a_constant_are_replaced_by_it() {
+        let mut module = Module {
+            functions: vec![Function {
+                name: "f".to_string(),
+                export: Some("f".to_string()),
+                file: String::new(),
+                inline: false,
+                signature: Signature {
+                    params: vec![DataType::I32],
+                    output: Some(DataType::I32),
+                },
+                locals: vec![DataType::I32],
+                body: vec![
+                    Expr::LocalSet(1, Box::new(binary(BinaryOp::Add, int(1), int(1)))),
+                    Expr::If(
+                        Some(DataType::I32),
+                        Box::new(binary(BinaryOp::Eq, Expr::LocalGet(1), int(2))),
+                        vec![Expr::LocalGet(0)],
+                        vec![int(0)],
+                    ),
+                ],
+            }],
+            ..Module::default()
+        };
+        fold_constants(&mut module);
+        assert_eq!(module.functions[0].body, vec![Expr::LocalGet(0)]);
+    }
+
+    #[test]
+    fn folded_code_computes_the_same() {
+        let source = "pub fn f(x) { a = (2 * 3) if (a == 6) { (x + (a - 1)) } else { 0 } }";
+        for level in crate::testing::OPT_LEVELS {
+            let wasm = crate::testing::compile_at(source, *level);
+            assert_eq!(
+                crate::testing::run(&wasm, "f", &[1.0]).0,
+                6.0,
+                "{:?}",
+                level
+            );
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::ir::*;
+
+/// functions no bigger than this many expressions are inlined without being
+/// marked `inline`, about the size of an accessor like `head` or `tail`
+pub const INLINE_SIZE: usize = 12;
+
+/// the most expressions a function can have for inlining it to never make
+/// the module bigger than calling it
+pub const SMALL_INLINE_SIZE: usize = 4;
+
+/// how many expressions a function body is made of
+fn size(f: &Function) -> usize {
+    let mut size = 0;
+    for x in f.body.iter() {
+        walk(x, 0, &mut |_, _| size += 1);
+    }
+    size
+}
+
+/// indices of the defined functions a function calls directly
+fn callees(f: &Function, imports: usize) -> Vec<usize> {
+    let mut callees = vec![];
+    for x in f.body.iter() {
+        walk(x, 0, &mut |x, _| {
+            if let Expr::Call(idx, _) = x {
+                let idx = *idx as usize;
+                if idx >= imports && !callees.contains(&(idx - imports)) {
+                    callees.push(idx - imports);
+                }
+            }
+        });
+    }
+    callees
+}
+
+/// functions ordered so every function comes after the ones it calls,
+/// except where they call each other in a cycle
+fn post_order(calls: &[Vec<usize>]) -> Vec<usize> {
+    fn visit(f: usize, calls: &[Vec<usize>], visited: &mut Vec<bool>, order: &mut Vec<usize>) {
+        if visited[f] {
+            return;
+        }
+        visited[f] = true;
+        for g in calls[f].iter() {
+            visit(*g, calls, visited, order);
+        }
+        order.push(f);
+    }
+    let mut visited = vec![false; calls.len()];
+    let mut order = vec![];
+    for f in 0..calls.len() {
+        visit(f, calls, &mut visited, &mut order);
+    }
+    order
+}
+
+/// can a function end up calling itself
+fn is_recursive(f: usize, calls: &[Vec<usize>]) -> bool {
+    let mut visited = vec![false; calls.len()];
+    let mut pending = calls[f].clone();
+    while let Some(g) = pending.pop() {
+        if g == f {
+            return true;
+        }
+        if !visited[g] {
+            visited[g] = true;
+            pending.extend(calls[g].iter());
+        }
+    }
+    false
+}
+
+/// the body of a function as an expression standing in for a call to it,
+/// with its params and locals placed after the locals of the caller starting
+/// at `offset`. Locals read before they are set start out as zero again, as
+/// they would for a call, even when the caller runs it more than once.
+fn substitute(callee: &Function, args: Vec<Expr>, offset: u32) -> Expr {
+    let types = callee
+        .signature
+        .params
+        .iter()
+        .chain(callee.locals.iter())
+        .collect::<Vec<_>>();
+    let zeroed = crate::locals::read_before_set(callee);
+    let mut body = callee.body.clone();
+    let mut leaves = false;
+    for x in body.iter_mut() {
+        walk_mut(x, 0, &mut |x, depth| match x {
+            Expr::LocalGet(i) => *i += offset,
+            Expr::LocalSet(i, _) | Expr::LocalTee(i, _) => *i += offset,
+            // returning from the callee is leaving the block it's put in
+            Expr::Return(v) => {
+                leaves = true;
+                *x = match v.take() {
+                    Some(v) => Expr::Sequence(vec![*v, Expr::Br(depth)]),
+                    None => Expr::Br(depth),
+                };
+            }
+            // so is branching to the callee's own label, which the block
+            // takes the place of at the same depth
+            Expr::Br(n) if *n == depth => leaves = true,
+            _ => {}
+        });
+    }
+    let mut e = args
+        .into_iter()
+        .enumerate()
+        .map(|(i, arg)| Expr::LocalSet(offset + i as u32, Box::new(arg)))
+        .collect::<Vec<Expr>>();
+    for i in zeroed {
+        let zero = Expr::Const(Value::zero(types[i as usize]));
+        e.push(Expr::LocalSet(offset + i, Box::new(zero)));
+    }
+    e.extend(body);
+    if leaves {
+        Expr::Block(callee.signature.output.clone(), e)
+    } else {
+        Expr::Sequence(e)
+    }
+}
+
+/// how many places each defined function is called from directly
+fn call_sites(module: &Module) -> Vec<usize> {
+    let imports = module.imports.len();
+    let mut sites = vec![0; module.functions.len()];
+    for f in module.functions.iter() {
+        for x in f.body.iter() {
+            walk(x, 0, &mut |x, _| {
+                if let Expr::Call(idx, _) = x {
+                    if *idx as usize >= imports {
+                        sites[*idx as usize - imports] += 1;
+                    }
+                }
+            });
+        }
+    }
+    sites
+}
+
+/// replace calls to functions of at most `max_size` expressions and
+/// functions marked `inline` with their bodies, and when `called_once` also
+/// functions only called from one place that nothing else can reach, which
+/// then go away. Functions that can call themselves are never inlined.
+pub fn inline_functions(module: &mut Module, max_size: usize, called_once: bool) {
+    let imports = module.imports.len();
+    let calls = module
+        .functions
+        .iter()
+        .map(|f| callees(f, imports))
+        .collect::<Vec<Vec<usize>>>();
+    let sites = call_sites(module);
+    let inlined = module
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let only_call = called_once
+                && sites[i] == 1
+                && f.export.is_none()
+                && !module.table.contains(&((imports + i) as u32));
+            (f.inline || size(f) <= max_size || only_call) && !is_recursive(i, &calls)
+        })
+        .collect::<Vec<bool>>();
+    if !inlined.iter().any(|x| *x) {
+        return;
+    }
+
+    // callees are done first so what gets substituted has nothing left to inline
+    for i in post_order(&calls) {
+        if !calls[i].iter().any(|g| inlined[*g]) {
+            continue;
+        }
+        let mut body = std::mem::take(&mut module.functions[i].body);
+        let caller = &module.functions[i];
+        let mut offset = (caller.signature.params.len() + caller.locals.len()) as u32;
+        let mut locals = vec![];
+        for x in body.iter_mut() {
+            walk_mut(x, 0, &mut |x, _| {
+                let g = match x {
+                    Expr::Call(idx, _) if *idx as usize >= imports => *idx as usize - imports,
+                    _ => return,
+                };
+                if !inlined[g] {
+                    return;
+                }
+                let callee = &module.functions[g];
+                let args = match x {
+                    Expr::Call(_, args) => std::mem::take(args),
+                    _ => return,
+                };
+                *x = substitute(callee, args, offset);
+                locals.extend(callee.signature.params.iter().cloned());
+                locals.extend(callee.locals.iter().cloned());
+                offset += (callee.signature.params.len() + callee.locals.len()) as u32;
+            });
+        }
+        let caller = &mut module.functions[i];
+        caller.locals.extend(locals);
+        caller.body = body;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmly::DataType;
+
+    fn function(name: &str, export: bool, body: Vec<Expr>) -> Function {
+        Function {
+            name: name.to_string(),
+            export: if export { Some(name.to_string()) } else { None },
+            file: String::new(),
+            inline: false,
+            signature: Signature {
+                params: vec![DataType::I32],
+                output: Some(DataType::F64),
+            },
+            locals: vec![],
+            body,
+        }
+    }
+
+    fn number(n: f64) -> Expr {
+        Expr::Const(Value::F64(n))
+    }
+
+    /// a callee leaving early by branching to its own label, inlined into a
+    /// caller that adds 10 to what it gives back
+    fn module() -> Module {
+        let callee = function(
+            "pick",
+            false,
+            vec![
+                Expr::If(
+                    None,
+                    Box::new(Expr::LocalGet(0)),
+                    vec![Expr::Sequence(vec![number(1.0), Expr::Br(1)])],
+                    vec![],
+                ),
+                number(2.0),
+            ],
+        );
+        let caller = function(
+            "f",
+            true,
+            vec![Expr::Binary(
+                BinaryOp::Add,
+                DataType::F64,
+                Box::new(Expr::Call(0, vec![Expr::LocalGet(0)])),
+                Box::new(number(10.0)),
+            )],
+        );
+        Module {
+            functions: vec![callee, caller],
+            ..Module::default()
+        }
+    }
+
+    #[test]
+    fn branches_to_the_callee_label_leave_only_the_inlined_body() {
+        let mut module = module();
+        inline_functions(&mut module, INLINE_SIZE, false);
+        let mut calls = 0;
+        walk(&module.functions[1].body[0], 0, &mut |x, _| {
+            if let Expr::Call(_, _) = x {
+                calls += 1;
+            }
+        });
+        assert_eq!(calls, 0);
+        let wasm = crate::backend::encode(&module);
+        assert_eq!(crate::testing::run(&wasm, "f", &[1.0]).0, 11.0);
+        assert_eq!(crate::testing::run(&wasm, "f", &[0.0]).0, 12.0);
+    }
+
+    #[test]
+    fn recursive_functions_are_not_inlined() {
+        let mut module = module();
+        module.functions[0].body = vec![Expr::Call(0, vec![Expr::LocalGet(0)])];
+        inline_functions(&mut module, INLINE_SIZE, true);
+        assert_eq!(
+            module.functions[0].body,
+            vec![Expr::Call(0, vec![Expr::LocalGet(0)])]
+        );
+        assert!(
+            matches!(module.functions[1].body[0], Expr::Binary(_, _, ref a, _) if matches!(**a, Expr::Call(0, _)))
+        );
+    }
+
+    #[test]
+    fn inlined_locals_start_out_as_zero_every_time() {
+        // only the first call sets b, the others read it as zero
+        let source = "fn step(c) { if c { b = 10 } b }
+pub fn f(n) {
+  mut i = 0
+  mut total = 0
+  loop { if (i < n) { total = (total + step((i == 0))) i = (i + 1) recur } else { total } }
+}";
+        for level in crate::testing::OPT_LEVELS {
+            let wasm = crate::testing::compile_at(source, *level);
+            assert_eq!(
+                crate::testing::run(&wasm, "f", &[5.0]).0,
+                10.0,
+                "{:?}",
+                level
+            );
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use wasmly::DataType;
+
+/// a constant of one of the web assembly types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Value {
+    /// the value a local of a type starts out as
+    pub fn zero(t: &DataType) -> Value {
+        match t {
+            DataType::I32 => Value::I32(0),
+            DataType::I64 => Value::I64(0),
+            DataType::F32 => Value::F32(0.0),
+            DataType::F64 => Value::F64(0.0),
+        }
+    }
+
+    pub fn data_type(&self) -> DataType {
+        match self {
+            Value::I32(_) => DataType::I32,
+            Value::I64(_) => DataType::I64,
+            Value::F32(_) => DataType::F32,
+            Value::F64(_) => DataType::F64,
+        }
+    }
+}
+
+/// operators taking two operands of the same type, integers are signed.
+/// Comparisons give back an i32, everything else the type of the operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl BinaryOp {
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge
+        )
+    }
+}
+
+/// the ways linear memory is read and written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    /// an unsigned byte as an i32
+    Byte,
+    /// a 64 bit float
+    F64,
+}
+
+impl Access {
+    pub fn data_type(self) -> DataType {
+        match self {
+            Access::Byte => DataType::I32,
+            Access::F64 => DataType::F64,
+        }
+    }
+}
+
+/// params and output of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<DataType>,
+    pub output: Option<DataType>,
+}
+
+/// a structured expression, each leaves at most one value on the stack
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Const(Value),
+    LocalGet(u32),
+    /// set a local leaving nothing
+    LocalSet(u32, Box<Expr>),
+    /// set a local leaving its new value
+    LocalTee(u32, Box<Expr>),
+    GlobalGet(u32),
+    GlobalSet(u32, Box<Expr>),
+    /// read memory at an address
+    Load(Access, Box<Expr>),
+    /// write a value to memory at an address, leaving nothing
+    Store(Access, Box<Expr>, Box<Expr>),
+    /// test an integer of the given type for zero
+    Eqz(DataType, Box<Expr>),
+    /// an operator on operands of the given type
+    Binary(BinaryOp, DataType, Box<Expr>, Box<Expr>),
+    /// convert a value from one type to another
+    Convert(DataType, DataType, Box<Expr>),
+    /// call a function by its index with arguments
+    Call(u32, Vec<Expr>),
+    /// call the function whose handle is the first expression, with a
+    /// signature from the module's types and arguments
+    CallIndirect(u32, Box<Expr>, Vec<Expr>),
+    /// expressions in order, only the last can leave a value
+    Sequence(Vec<Expr>),
+    /// a sequence that can be broken out of
+    Block(Option<DataType>, Vec<Expr>),
+    /// a condition and the sequences for when it's true and false
+    If(Option<DataType>, Box<Expr>, Vec<Expr>, Vec<Expr>),
+    /// a sequence that is restarted when branched to
+    Loop(Option<DataType>, Vec<Expr>),
+    /// branch to a surrounding block, if or loop counting outwards from 0
+    Br(u32),
+    Return(Option<Box<Expr>>),
+    /// a call or indirect call made in place of the current function,
+    /// returning whatever it returns
+    TailCall(Box<Expr>),
+    Drop(Box<Expr>),
+}
+
+impl Expr {
+    /// does control never continue after this expression
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            Expr::Br(_) | Expr::Return(_) | Expr::TailCall(_) => true,
+            Expr::Sequence(e) => e.last().map(Expr::is_unreachable).unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+/// visit an expression and everything in it, with how many labels deep it is
+pub fn walk(e: &Expr, depth: u32, f: &mut impl FnMut(&Expr, u32)) {
+    f(e, depth);
+    match e {
+        Expr::LocalSet(_, v)
+        | Expr::LocalTee(_, v)
+        | Expr::GlobalSet(_, v)
+        | Expr::Load(_, v)
+        | Expr::Eqz(_, v)
+        | Expr::Convert(_, _, v)
+        | Expr::Drop(v)
+        | Expr::Return(Some(v))
+        | Expr::TailCall(v) => walk(v, depth, f),
+        Expr::Store(_, a, b) | Expr::Binary(_, _, a, b) => {
+            walk(a, depth, f);
+            walk(b, depth, f);
+        }
+        Expr::Call(_, args) | Expr::Sequence(args) => {
+            for a in args.iter() {
+                walk(a, depth, f);
+            }
+        }
+        Expr::CallIndirect(_, callee, args) => {
+            for a in args.iter() {
+                walk(a, depth, f);
+            }
+            walk(callee, depth, f);
+        }
+        Expr::Block(_, e) | Expr::Loop(_, e) => {
+            for x in e.iter() {
+                walk(x, depth + 1, f);
+            }
+        }
+        Expr::If(_, condition, if_true, if_false) => {
+            walk(condition, depth, f);
+            for x in if_true.iter().chain(if_false.iter()) {
+                walk(x, depth + 1, f);
+            }
+        }
+        Expr::Const(_)
+        | Expr::LocalGet(_)
+        | Expr::GlobalGet(_)
+        | Expr::Br(_)
+        | Expr::Return(None) => {}
+    }
+}
+
+/// like walk, but able to change what is visited
+pub fn walk_mut(e: &mut Expr, depth: u32, f: &mut impl FnMut(&mut Expr, u32)) {
+    f(e, depth);
+    match e {
+        Expr::LocalSet(_, v)
+        | Expr::LocalTee(_, v)
+        | Expr::GlobalSet(_, v)
+        | Expr::Load(_, v)
+        | Expr::Eqz(_, v)
+        | Expr::Convert(_, _, v)
+        | Expr::Drop(v)
+        | Expr::Return(Some(v))
+        | Expr::TailCall(v) => walk_mut(v, depth, f),
+        Expr::Store(_, a, b) | Expr::Binary(_, _, a, b) => {
+            walk_mut(a, depth, f);
+            walk_mut(b, depth, f);
+        }
+        Expr::Call(_, args) | Expr::Sequence(args) => {
+            for a in args.iter_mut() {
+                walk_mut(a, depth, f);
+            }
+        }
+        Expr::CallIndirect(_, callee, args) => {
+            for a in args.iter_mut() {
+                walk_mut(a, depth, f);
+            }
+            walk_mut(callee, depth, f);
+        }
+        Expr::Block(_, e) | Expr::Loop(_, e) => {
+            for x in e.iter_mut() {
+                walk_mut(x, depth + 1, f);
+            }
+        }
+        Expr::If(_, condition, if_true, if_false) => {
+            walk_mut(condition, depth, f);
+            for x in if_true.iter_mut().chain(if_false.iter_mut()) {
+                walk_mut(x, depth + 1, f);
+            }
+        }
+        Expr::Const(_)
+        | Expr::LocalGet(_)
+        | Expr::GlobalGet(_)
+        | Expr::Br(_)
+        | Expr::Return(None) => {}
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// linked name of the extern
+    pub name: String,
+    /// name it is imported from the host by
+    pub field: String,
+    pub signature: Signature,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// linked name of the function
+    pub name: String,
+    /// name it is exported to the host by
+    pub export: Option<String>,
+    /// source file the function is written in
+    pub file: String,
+    /// substitute the body at every call even if it's large
+    pub inline: bool,
+    pub signature: Signature,
+    /// types of the locals after the params
+    pub locals: Vec<DataType>,
+    pub body: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub mutable: bool,
+    pub value: Value,
+}
+
+/// what put data in memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataOrigin {
+    /// a string literal
+    Text,
+    /// the data of a `static`
+    Static,
+    /// the member table of a `struct`
+    Struct,
+}
+
+/// bytes placed in memory when the module starts
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub offset: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// where something was put in memory
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub offset: u32,
+    pub size: u32,
+    pub origin: DataOrigin,
+    /// the text of a string literal or the name of a static
+    pub label: String,
+    /// uses the data of something placed earlier at the same address
+    pub shared: bool,
+}
+
+/// everything a web assembly module is made of, functions are indexed
+/// after imports
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    /// signatures indirect calls are made with
+    pub types: Vec<Signature>,
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    /// function indices handles refer to
+    pub table: Vec<u32>,
+    pub globals: Vec<Global>,
+    pub data: Vec<Data>,
+    /// what each part of the data is
+    pub placements: Vec<Placement>,
+    /// first address after the data, where the heap starts
+    pub heap_start: u32,
+    /// initial size of linear memory in 64KiB pages
+    pub initial_memory: u32,
+    /// maximum size of linear memory in 64KiB pages
+    pub max_memory: u32,
+    /// linear memory is given by the host as `env.memory` instead of being
+    /// created by the module
+    pub import_memory: bool,
+    /// linear memory can be used by several threads at once
+    pub shared_memory: bool,
+    /// the name linear memory is exported to the host as
+    pub memory_export: Option<String>,
+    /// include the names of functions for debuggers and stack traces
+    pub names: bool,
+}
+
+impl Module {
+    /// index of a signature in the module's types, adding it if needed
+    pub fn type_index(&mut self, signature: Signature) -> u32 {
+        match self.types.iter().position(|t| *t == signature) {
+            Some(i) => i as u32,
+            None => {
+                self.types.push(signature);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+}
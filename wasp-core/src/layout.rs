@@ -0,0 +1,190 @@
+use crate::ir::{Data, DataOrigin, Placement};
+
+/// where the data of a module goes in linear memory, which starts at a
+/// fixed address and is followed by the heap
+pub struct Layout {
+    placements: Vec<Placement>,
+    bytes: Vec<Vec<u8>>,
+    /// whether other data may be placed at the same address as each placement
+    shareable: Vec<bool>,
+    end: u32,
+}
+
+/// addresses below this are never used so nothing has the address 0
+pub const DATA_START: u32 = 4;
+
+/// numbers are f64 and are read fastest from addresses that are a multiple
+/// of their size
+pub const NUMBER_ALIGNMENT: u32 = 8;
+
+fn align(position: u32, alignment: u32) -> u32 {
+    position.div_ceil(alignment) * alignment
+}
+
+/// the alignment data of an origin needs
+fn alignment(origin: DataOrigin) -> u32 {
+    match origin {
+        DataOrigin::Text => 1,
+        DataOrigin::Static | DataOrigin::Struct => NUMBER_ALIGNMENT,
+    }
+}
+
+impl Layout {
+    pub fn new(start: u32) -> Layout {
+        Layout {
+            placements: vec![],
+            bytes: vec![],
+            shareable: vec![],
+            end: start,
+        }
+    }
+
+    /// the address of some data, placing it after everything placed so far
+    /// unless `share` and the same data of the same origin, also placed with
+    /// `share`, is already there
+    pub fn place(&mut self, bytes: Vec<u8>, origin: DataOrigin, label: String, share: bool) -> u32 {
+        if share {
+            let existing = self
+                .placements
+                .iter()
+                .zip(self.bytes.iter())
+                .zip(self.shareable.iter())
+                .find(|((p, b), shareable)| {
+                    **shareable && !p.shared && p.origin == origin && **b == bytes
+                })
+                .map(|((p, _), _)| p.offset);
+            if let Some(offset) = existing {
+                self.placements.push(Placement {
+                    offset,
+                    size: bytes.len() as u32,
+                    origin,
+                    label,
+                    shared: true,
+                });
+                self.bytes.push(vec![]);
+                self.shareable.push(true);
+                return offset;
+            }
+        }
+        let offset = align(self.end, alignment(origin));
+        self.end = offset + bytes.len() as u32;
+        self.placements.push(Placement {
+            offset,
+            size: bytes.len() as u32,
+            origin,
+            label,
+            shared: false,
+        });
+        self.bytes.push(bytes);
+        self.shareable.push(share);
+        offset
+    }
+
+    /// the first address after the data, where the heap can start
+    pub fn heap_start(&self) -> u32 {
+        align(self.end, NUMBER_ALIGNMENT)
+    }
+
+    /// everything placed, in the order it was
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    /// the data as segments to put in the module, data separated only by
+    /// alignment padding goes in the same segment
+    pub fn segments(&self) -> Vec<Data> {
+        let mut segments: Vec<Data> = vec![];
+        for (p, bytes) in self.placements.iter().zip(self.bytes.iter()) {
+            if p.shared || bytes.is_empty() {
+                continue;
+            }
+            match segments.last_mut() {
+                Some(s) if s.offset + s.bytes.len() as u32 + NUMBER_ALIGNMENT > p.offset => {
+                    let padding = p.offset - s.offset - s.bytes.len() as u32;
+                    s.bytes.extend(std::iter::repeat_n(0, padding as usize));
+                    s.bytes.extend_from_slice(bytes);
+                }
+                _ => segments.push(Data {
+                    offset: p.offset,
+                    bytes: bytes.clone(),
+                }),
+            }
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(n: f64) -> Vec<u8> {
+        n.to_le_bytes().to_vec()
+    }
+
+    #[test]
+    fn numbers_are_placed_at_multiples_of_their_size() {
+        let mut layout = Layout::new(DATA_START);
+        let text = layout.place(b"hi\0".to_vec(), DataOrigin::Text, "hi".to_string(), true);
+        let first = layout.place(number(1.0), DataOrigin::Static, "a".to_string(), true);
+        let more = layout.place(
+            b"hello\0".to_vec(),
+            DataOrigin::Text,
+            "hello".to_string(),
+            true,
+        );
+        let second = layout.place(number(2.0), DataOrigin::Struct, "b".to_string(), true);
+        assert_eq!((text, first, more, second), (4, 8, 16, 24));
+        assert_eq!(layout.heap_start(), 32);
+        // only padding between them, so they are written as one segment
+        let segments = layout.segments();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].offset, 4);
+        assert_eq!(segments[0].bytes.len(), 28);
+        assert_eq!(&segments[0].bytes[4..12], &number(1.0)[..]);
+    }
+
+    #[test]
+    fn identical_data_of_the_same_origin_is_shared_when_asked() {
+        let mut layout = Layout::new(DATA_START);
+        let a = layout.place(number(1.0), DataOrigin::Static, "a".to_string(), true);
+        let b = layout.place(number(1.0), DataOrigin::Static, "b".to_string(), true);
+        let c = layout.place(number(1.0), DataOrigin::Struct, "c".to_string(), true);
+        let d = layout.place(number(1.0), DataOrigin::Static, "d".to_string(), false);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+        let shared = layout
+            .placements()
+            .iter()
+            .map(|p| p.shared)
+            .collect::<Vec<bool>>();
+        assert_eq!(shared, vec![false, true, false, false]);
+        // data that isn't to be shared isn't shared with either
+        let e = layout.place(number(2.0), DataOrigin::Static, "e".to_string(), false);
+        let f = layout.place(number(2.0), DataOrigin::Static, "f".to_string(), true);
+        assert_ne!(e, f);
+        let bytes = layout
+            .segments()
+            .iter()
+            .map(|s| s.bytes.len())
+            .sum::<usize>();
+        assert_eq!(bytes, 40);
+    }
+
+    #[test]
+    fn statics_are_read_at_every_level() {
+        let source = "static text = \"x\"
+static numbers = (1.5, 2.5)
+pub fn f(i) { (mem((numbers + (i * size_num))) + mem_byte(text)) }";
+        for level in crate::testing::OPT_LEVELS {
+            let wasm = crate::testing::compile_at(source, *level);
+            assert_eq!(
+                crate::testing::run(&wasm, "f", &[1.0]).0,
+                122.5,
+                "{:?}",
+                level
+            );
+        }
+    }
+}
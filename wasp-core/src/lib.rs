@@ -2,6 +2,28 @@
 extern crate failure;
 #[macro_use]
 extern crate nom;
+pub mod arity;
 pub mod ast;
+pub mod backend;
 pub mod compiler;
-pub mod parser;
\ No newline at end of file
+pub mod definitions;
+pub mod fold;
+pub mod inline;
+pub mod ir;
+pub mod layout;
+pub mod lint;
+pub mod locals;
+pub mod memory_map;
+pub mod merge;
+pub mod modules;
+pub mod mutability;
+pub mod parser;
+pub mod peephole;
+pub mod read_only;
+pub mod resolve;
+pub mod shake;
+pub mod signatures;
+pub mod size;
+pub mod tail;
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,384 @@
+use crate::ast::*;
+use crate::modules::unqualified_name;
+use failure::Error;
+use std::collections::HashMap;
+use std::fmt;
+
+pub const UNUSED_VARIABLES: &str = "unused_variables";
+pub const UNUSED_FUNCTIONS: &str = "unused_functions";
+pub const UNUSED_EXTERNS: &str = "unused_externs";
+pub const SHADOWED_GLOBALS: &str = "shadowed_globals";
+pub const RECUR_OUTSIDE_LOOP: &str = "recur_outside_loop";
+/// the group of every lint
+pub const WARNINGS: &str = "warnings";
+
+/// every lint the compiler knows about
+pub const LINTS: &[&str] = &[
+    UNUSED_VARIABLES,
+    UNUSED_FUNCTIONS,
+    UNUSED_EXTERNS,
+    SHADOWED_GLOBALS,
+    RECUR_OUTSIDE_LOOP,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+pub fn is_lint(id: &str) -> bool {
+    id == WARNINGS || LINTS.contains(&id)
+}
+
+/// something that compiles but probably isn't what was meant
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub lint: &'static str,
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// the level of every lint, starting from the project wide settings
+#[derive(Clone)]
+struct Levels(HashMap<&'static str, Level>);
+
+impl Levels {
+    fn set(&mut self, id: &str, level: Level) {
+        for lint in LINTS.iter() {
+            if id == WARNINGS || id == *lint {
+                self.0.insert(lint, level);
+            }
+        }
+    }
+
+    fn get(&self, lint: &str) -> Level {
+        self.0.get(lint).cloned().unwrap_or(Level::Warn)
+    }
+
+    /// apply attributes like `#[allow(unused_variables)]` of a definition
+    fn with_attributes(&self, attributes: &[Attribute], errors: &mut Vec<String>) -> Levels {
+        let mut levels = self.clone();
+        for a in attributes.iter() {
+            let level = match Level::from_name(&a.name) {
+                Some(l) => l,
+                None => {
+                    errors.push(format!("{}: unknown attribute {}", a.location, a.name));
+                    continue;
+                }
+            };
+            for id in a.args.iter() {
+                if is_lint(id) {
+                    levels.set(id, level);
+                } else {
+                    errors.push(format!("{}: unknown lint {}", a.location, id));
+                }
+            }
+        }
+        levels
+    }
+}
+
+/// the module a linked name such as `std::list::cons` was defined in
+fn module_of(name: &str) -> &str {
+    name.rsplit_once("::").map(|(m, _)| m).unwrap_or("")
+}
+
+struct Linter<'a> {
+    globals: &'a [&'a Global],
+    /// names referenced from each function, by the function that references them
+    references: HashMap<&'a str, Vec<&'a str>>,
+    warnings: Vec<(Level, Warning)>,
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, levels: &Levels, lint: &'static str, location: &Location, message: String) {
+        let level = levels.get(lint);
+        if level != Level::Allow {
+            self.warnings.push((
+                level,
+                Warning {
+                    lint,
+                    location: location.clone(),
+                    message,
+                },
+            ));
+        }
+    }
+
+    fn collect_references(&mut self, from: &'a str, e: &'a [Expression]) {
+        for x in e.iter() {
+            walk(x, 0, &mut |x, _| match x {
+                Expression::Identifier(x) => self.reference(&x.name, from),
+                Expression::FunctionCall(x) => self.reference(&x.function_name, from),
+                _ => {}
+            });
+        }
+    }
+
+    fn reference(&mut self, name: &'a str, from: &'a str) {
+        self.references.entry(name).or_default().push(from);
+    }
+
+    /// is a definition used by anything other than itself
+    fn is_used(&self, name: &str) -> bool {
+        match self.references.get(name) {
+            Some(r) => r.iter().any(|from| *from != name),
+            None => false,
+        }
+    }
+
+    fn lint_function(&mut self, f: &'a FunctionDefinition, levels: &Levels) {
+        let mut warned: Vec<&str> = vec![];
+        for a in assignments(&f.children) {
+            if warned.contains(&a.id.as_str()) {
+                continue;
+            }
+            warned.push(&a.id);
+            let is_read = self
+                .references
+                .get(a.id.as_str())
+                .map(|r| r.contains(&f.name.as_str()))
+                .unwrap_or(false);
+            if !is_read && !a.id.starts_with('_') {
+                self.warn(
+                    levels,
+                    UNUSED_VARIABLES,
+                    &a.location,
+                    format!(
+                        "{} is assigned but never read, prefix it with _ if that is intended",
+                        a.id
+                    ),
+                );
+            }
+            let module = module_of(&f.name);
+            let shadowed = self.globals.iter().find(|g| {
+                unqualified_name(&g.name) == a.id && (g.public || module_of(&g.name) == module)
+            });
+            if let Some(g) = shadowed {
+                let message = format!(
+                    "{} shadows the static of the same name\n  {}: static defined here",
+                    a.id, g.location
+                );
+                self.warn(levels, SHADOWED_GLOBALS, &a.location, message);
+            }
+        }
+        for x in f.children.iter() {
+            walk(x, 0, &mut |x, loops| {
+                if let Expression::Recur(r) = x {
+                    if loops == 0 {
+                        self.warn(
+                            levels,
+                            RECUR_OUTSIDE_LOOP,
+                            &r.location,
+                            "recur outside of a loop returns from the function instead".to_string(),
+                        );
+                    }
+                }
+            });
+        }
+    }
+}
+
+fn collect_global_references<'a>(v: &'a GlobalValue, references: &mut Vec<&'a str>) {
+    match v {
+        GlobalValue::Identifier(name) => references.push(name),
+        GlobalValue::Data(values) => {
+            for v in values.iter() {
+                collect_global_references(v, references);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// look for code that is probably a mistake, `lints` are the project wide
+/// levels applied in order. Denied lints are returned as errors.
+pub fn check_lints(app: &App, lints: &[(String, Level)]) -> Result<Vec<Warning>, Error> {
+    let mut errors = vec![];
+    let mut levels = Levels(HashMap::new());
+    for (id, level) in lints.iter() {
+        if is_lint(id) {
+            levels.set(id, *level);
+        } else {
+            errors.push(format!("unknown lint {}", id));
+        }
+    }
+
+    let globals = app
+        .children
+        .iter()
+        .filter_map(|op| match op {
+            TopLevelOperation::DefineGlobal(g) => Some(g),
+            _ => None,
+        })
+        .collect::<Vec<&Global>>();
+    let mut linter = Linter {
+        globals: &globals,
+        references: HashMap::new(),
+        warnings: vec![],
+    };
+    for op in app.children.iter() {
+        match op {
+            TopLevelOperation::DefineFunction(f) => linter.collect_references(&f.name, &f.children),
+            TopLevelOperation::DefineGlobal(g) => {
+                let mut references = vec![];
+                collect_global_references(&g.value, &mut references);
+                for r in references {
+                    linter.reference(r, &g.name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for op in app.children.iter() {
+        match op {
+            TopLevelOperation::DefineFunction(f) => {
+                let levels = levels.with_attributes(&f.attributes, &mut errors);
+                if !f.exported && !linter.is_used(&f.name) {
+                    linter.warn(
+                        &levels,
+                        UNUSED_FUNCTIONS,
+                        &f.location,
+                        format!("function {} is never used", unqualified_name(&f.name)),
+                    );
+                }
+                linter.lint_function(f, &levels);
+            }
+            TopLevelOperation::ExternalFunction(f) => {
+                let levels = levels.with_attributes(&f.attributes, &mut errors);
+                if !linter.is_used(&f.name) {
+                    linter.warn(
+                        &levels,
+                        UNUSED_EXTERNS,
+                        &f.location,
+                        format!("extern {} is never used", unqualified_name(&f.name)),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut warnings = vec![];
+    for (level, w) in linter.warnings {
+        if level == Level::Deny {
+            errors.push(format!("{} [denied by {}]", w, w.lint));
+        } else {
+            warnings.push(w);
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format_err!("{}", errors.join("\n")));
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::{link, Module};
+
+    fn lint(source: &str, lints: &[(&str, Level)]) -> Result<Vec<String>, String> {
+        let app = crate::parser::parse(source).expect("source parses");
+        let lints = lints
+            .iter()
+            .map(|(id, level)| (id.to_string(), *level))
+            .collect::<Vec<(String, Level)>>();
+        check_lints(&app, &lints)
+            .map(|w| w.iter().map(|w| w.to_string()).collect())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn unused_definitions_are_named_as_they_were_written() {
+        let module = |file: &str, source: &str, entry: bool| Module {
+            path: vec!["p".to_string(), file.trim_end_matches(".w").to_string()],
+            root: true,
+            entry,
+            app: crate::parser::parse_file(file, source).expect("source parses"),
+        };
+        let app = link(vec![
+            module("main.w", "pub fn main() { 1 }", true),
+            module("a.w", "extern log(x)\nfn helper() { 1 }", false),
+        ])
+        .unwrap();
+        let warnings = check_lints(&app, &[])
+            .unwrap()
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            warnings,
+            vec![
+                "a.w:1:1: extern log is never used",
+                "a.w:2:1: function helper is never used"
+            ]
+        );
+    }
+
+    #[test]
+    fn lints_are_allowed_by_attributes() {
+        let source = "pub fn main() { x = 1 }\n#[allow(unused_functions)]\nfn helper() { 1 }";
+        assert_eq!(
+            lint(source, &[]).unwrap(),
+            vec!["1:17: x is assigned but never read, prefix it with _ if that is intended"]
+        );
+        let source =
+            "#[allow(warnings)]\npub fn main() { x = 1 }\n#[allow(warnings)]\nfn helper() { 1 }";
+        assert!(lint(source, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn lints_are_denied_by_the_project_lints() {
+        let source = "pub fn main() { 1 }\nfn helper() { 1 }";
+        assert_eq!(
+            lint(source, &[(UNUSED_FUNCTIONS, Level::Deny)]).unwrap_err(),
+            "2:1: function helper is never used [denied by unused_functions]"
+        );
+        assert!(lint(source, &[(WARNINGS, Level::Allow)])
+            .unwrap()
+            .is_empty());
+        // attributes override the project lints
+        let source = "pub fn main() { 1 }\n#[warn(unused_functions)]\nfn helper() { 1 }";
+        assert_eq!(
+            lint(source, &[(WARNINGS, Level::Deny)]).unwrap(),
+            vec!["3:1: function helper is never used"]
+        );
+    }
+
+    #[test]
+    fn unknown_lints_are_errors() {
+        assert_eq!(
+            lint("pub fn main() { 1 }", &[("unused_fnuctions", Level::Deny)]).unwrap_err(),
+            "unknown lint unused_fnuctions"
+        );
+        assert_eq!(
+            lint("#[allow(unused_fnuctions)]\npub fn main() { 1 }", &[]).unwrap_err(),
+            "1:1: unknown lint unused_fnuctions"
+        );
+        assert_eq!(
+            lint("#[silence(unused_functions)]\npub fn main() { 1 }", &[]).unwrap_err(),
+            "1:1: unknown attribute silence"
+        );
+    }
+}
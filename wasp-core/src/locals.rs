@@ -0,0 +1,384 @@
+use crate::ir::*;
+use wasmly::DataType;
+
+/// a read or write of a local
+struct Access {
+    local: u32,
+    position: usize,
+    set: bool,
+    /// the loops and conditionally run code the access is in, outermost first
+    frames: Vec<Frame>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Frame {
+    Loop(usize),
+    Conditional(usize),
+}
+
+/// where every local is read and written in a function, in the order it
+/// happens, along with the positions every loop spans
+struct Liveness {
+    accesses: Vec<Access>,
+    loops: Vec<(usize, usize)>,
+    frames: Vec<Frame>,
+    /// how many conditionally run sequences have been entered
+    conditionals: usize,
+    position: usize,
+}
+
+impl Liveness {
+    fn access(&mut self, local: u32, set: bool) {
+        self.position += 1;
+        self.accesses.push(Access {
+            local,
+            position: self.position,
+            set,
+            frames: self.frames.clone(),
+        });
+    }
+
+    fn visit_block(&mut self, e: &[Expr]) {
+        for x in e.iter() {
+            self.visit(x);
+        }
+    }
+
+    fn visit_frame(&mut self, frame: Frame, e: &[Expr]) {
+        self.frames.push(frame);
+        self.visit_block(e);
+        self.frames.pop();
+    }
+
+    fn visit_conditional(&mut self, e: &[Expr]) {
+        self.conditionals += 1;
+        self.visit_frame(Frame::Conditional(self.conditionals), e);
+    }
+
+    fn visit(&mut self, e: &Expr) {
+        match e {
+            Expr::LocalGet(i) => self.access(*i, false),
+            // the value is computed before the local changes
+            Expr::LocalSet(i, v) | Expr::LocalTee(i, v) => {
+                self.visit(v);
+                self.access(*i, true);
+            }
+            Expr::GlobalSet(_, v)
+            | Expr::Load(_, v)
+            | Expr::Eqz(_, v)
+            | Expr::Convert(_, _, v)
+            | Expr::Drop(v)
+            | Expr::Return(Some(v))
+            | Expr::TailCall(v) => self.visit(v),
+            Expr::Store(_, a, b) | Expr::Binary(_, _, a, b) => {
+                self.visit(a);
+                self.visit(b);
+            }
+            Expr::Call(_, args) | Expr::Sequence(args) => self.visit_block(args),
+            Expr::CallIndirect(_, callee, args) => {
+                self.visit_block(args);
+                self.visit(callee);
+            }
+            // code after a branch out of a block may not run
+            Expr::Block(_, e) => self.visit_conditional(e),
+            Expr::If(_, condition, if_true, if_false) => {
+                self.visit(condition);
+                self.visit_conditional(if_true);
+                self.visit_conditional(if_false);
+            }
+            Expr::Loop(_, e) => {
+                let id = self.loops.len();
+                self.loops.push((self.position + 1, 0));
+                self.visit_frame(Frame::Loop(id), e);
+                self.loops[id].1 = self.position;
+            }
+            Expr::Const(_) | Expr::GlobalGet(_) | Expr::Br(_) | Expr::Return(None) => {}
+        }
+    }
+}
+
+/// the positions between which each local holds a value that may still be
+/// read, None for locals that are never read
+fn live_ranges(f: &Function) -> Vec<Option<(usize, usize)>> {
+    let mut liveness = Liveness {
+        accesses: vec![],
+        loops: vec![],
+        frames: vec![],
+        conditionals: 0,
+        position: 0,
+    };
+    liveness.visit_block(&f.body);
+    let count = f.signature.params.len() + f.locals.len();
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; count];
+    for a in liveness.accesses.iter() {
+        let r = ranges[a.local as usize].get_or_insert((a.position, a.position));
+        r.0 = r.0.min(a.position);
+        r.1 = r.1.max(a.position);
+    }
+    for (i, r) in ranges.iter_mut().enumerate() {
+        let accesses = liveness
+            .accesses
+            .iter()
+            .filter(|a| a.local as usize == i)
+            .collect::<Vec<&Access>>();
+        let mut reads = accesses.iter().filter(|a| !a.set).peekable();
+        if reads.peek().is_none() {
+            *r = None;
+            continue;
+        }
+        match accesses.first() {
+            // set in code that always runs before any of the reads
+            Some(a) if a.set && reads.all(|x| x.frames.starts_with(&a.frames)) => {}
+            // may be read before it's set, relying on starting out as zero
+            _ => *r = r.map(|(_, end)| (0, end)),
+        }
+    }
+
+    // inner loops end first, so what they extend is seen by the loops around them
+    let mut loops = liveness.loops.iter().enumerate().collect::<Vec<_>>();
+    loops.sort_by_key(|(_, (start, end))| (*end, std::cmp::Reverse(*start)));
+    for (id, (start, end)) in loops {
+        for r in ranges.iter_mut() {
+            let (first, last) = match r {
+                Some(r) if r.1 >= *start && r.0 <= *end => *r,
+                _ => continue,
+            };
+            // a value set at the start of every iteration and only read
+            // within it never needs to survive going around the loop
+            let fresh_each_iteration = first >= *start
+                && last <= *end
+                && liveness
+                    .accesses
+                    .iter()
+                    .find(|a| a.position == first)
+                    .map(|a| {
+                        a.set && {
+                            let inner = a.frames.iter().skip_while(|f| **f != Frame::Loop(id));
+                            !inner.skip(1).any(|f| matches!(f, Frame::Conditional(_)))
+                        }
+                    })
+                    .unwrap_or(false);
+            if !fresh_each_iteration {
+                *r = Some((first.min(*start), last.max(*end)));
+            }
+        }
+    }
+    ranges
+}
+
+/// the locals of a function, not counting its params, that may be read before
+/// they are set and so rely on starting out as zero
+pub fn read_before_set(f: &Function) -> Vec<u32> {
+    let params = f.signature.params.len();
+    live_ranges(f)
+        .iter()
+        .enumerate()
+        .skip(params)
+        .filter(|(_, r)| matches!(r, Some((0, _))))
+        .map(|(i, _)| i as u32)
+        .collect()
+}
+
+/// the local each local is moved to, sharing a slot with others of the same
+/// type that are never live at the same time. Locals live from the start of
+/// the function rely on starting out as zero and get a slot of their own.
+fn allocate(f: &Function, ranges: &[Option<(usize, usize)>]) -> (Vec<Option<u32>>, Vec<DataType>) {
+    let params = f.signature.params.len();
+    let types = f
+        .signature
+        .params
+        .iter()
+        .chain(f.locals.iter())
+        .collect::<Vec<&DataType>>();
+    let mut slots: Vec<Option<u32>> = (0..params as u32).map(Some).collect();
+    slots.resize(types.len(), None);
+    let mut order = (params..types.len())
+        .filter_map(|i| ranges[i].map(|r| (i, r)))
+        .collect::<Vec<(usize, (usize, usize))>>();
+    order.sort_by_key(|(_, (start, _))| *start);
+    // the type of every slot after the params and when it is next free
+    let mut free: Vec<(DataType, usize)> = vec![];
+    for (i, (start, end)) in order {
+        let slot = match free
+            .iter()
+            .position(|(t, until)| t == types[i] && *until < start)
+        {
+            Some(s) => s,
+            None => {
+                free.push((types[i].clone(), 0));
+                free.len() - 1
+            }
+        };
+        free[slot].1 = if start == 0 { usize::MAX } else { end };
+        slots[i] = Some((params + slot) as u32);
+    }
+    (slots, free.into_iter().map(|(t, _)| t).collect())
+}
+
+/// renumber the locals of every function so locals that are never live at the
+/// same time share a slot and ones never read go away
+pub fn allocate_locals(module: &mut Module) {
+    for f in module.functions.iter_mut() {
+        let ranges = live_ranges(f);
+        let (slots, locals) = allocate(f, &ranges);
+        let unread = |x: &Expr| match x {
+            Expr::LocalSet(i, _) | Expr::LocalTee(i, _) => slots[*i as usize].is_none(),
+            _ => false,
+        };
+        for x in f.body.iter_mut() {
+            walk_mut(x, 0, &mut |x, _| {
+                // setting a local nobody reads only needs the value's effects
+                while unread(x) {
+                    *x = match std::mem::replace(x, Expr::Sequence(vec![])) {
+                        Expr::LocalSet(_, v) => Expr::Drop(v),
+                        Expr::LocalTee(_, v) => *v,
+                        x => x,
+                    };
+                }
+                match x {
+                    Expr::LocalGet(i) | Expr::LocalSet(i, _) | Expr::LocalTee(i, _) => {
+                        *i = slots[*i as usize].expect("local is read")
+                    }
+                    _ => {}
+                }
+            });
+        }
+        f.locals = locals;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(params: usize, locals: usize, body: Vec<Expr>) -> Function {
+        Function {
+            name: "f".to_string(),
+            export: None,
+            file: String::new(),
+            inline: false,
+            signature: Signature {
+                params: vec![DataType::F64; params],
+                output: Some(DataType::F64),
+            },
+            locals: vec![DataType::F64; locals],
+            body,
+        }
+    }
+
+    fn set(local: u32, v: Expr) -> Expr {
+        Expr::LocalSet(local, Box::new(v))
+    }
+
+    fn number(n: f64) -> Expr {
+        Expr::Const(Value::F64(n))
+    }
+
+    #[test]
+    fn temporaries_one_after_another_share_a_slot() {
+        let f = function(
+            0,
+            2,
+            vec![
+                set(0, number(1.0)),
+                Expr::Drop(Box::new(Expr::LocalGet(0))),
+                set(1, number(2.0)),
+                Expr::LocalGet(1),
+            ],
+        );
+        let ranges = live_ranges(&f);
+        assert_eq!(ranges, vec![Some((1, 2)), Some((3, 4))]);
+        let (slots, locals) = allocate(&f, &ranges);
+        assert_eq!(slots, vec![Some(0), Some(0)]);
+        assert_eq!(locals.len(), 1);
+    }
+
+    #[test]
+    fn locals_never_read_get_no_slot() {
+        let f = function(0, 1, vec![set(0, number(1.0)), number(0.0)]);
+        let ranges = live_ranges(&f);
+        assert_eq!(ranges, vec![None]);
+        let (slots, locals) = allocate(&f, &ranges);
+        assert_eq!(slots, vec![None]);
+        assert!(locals.is_empty());
+    }
+
+    #[test]
+    fn locals_set_before_a_loop_live_through_it() {
+        let f = function(
+            0,
+            1,
+            vec![
+                set(0, number(1.0)),
+                Expr::Loop(
+                    None,
+                    vec![Expr::Drop(Box::new(Expr::LocalGet(0))), Expr::Br(0)],
+                ),
+                number(0.0),
+            ],
+        );
+        assert_eq!(live_ranges(&f), vec![Some((1, 2))]);
+    }
+
+    #[test]
+    fn locals_set_in_a_branch_keep_their_own_zeroed_slot() {
+        // fn f(c){ a=(c+5) console_log(a) if c { b=(c+0.5) } b }
+        let f = function(
+            1,
+            2,
+            vec![
+                set(
+                    1,
+                    Expr::Binary(
+                        BinaryOp::Add,
+                        DataType::F64,
+                        Box::new(Expr::LocalGet(0)),
+                        Box::new(number(5.0)),
+                    ),
+                ),
+                Expr::Drop(Box::new(Expr::Call(0, vec![Expr::LocalGet(1)]))),
+                Expr::If(
+                    None,
+                    Box::new(Expr::Const(Value::I32(1))),
+                    vec![set(
+                        2,
+                        Expr::Binary(
+                            BinaryOp::Add,
+                            DataType::F64,
+                            Box::new(Expr::LocalGet(0)),
+                            Box::new(number(0.5)),
+                        ),
+                    )],
+                    vec![],
+                ),
+                Expr::LocalGet(2),
+            ],
+        );
+        let ranges = live_ranges(&f);
+        assert_eq!(ranges[1], Some((2, 3)));
+        assert_eq!(ranges[2], Some((0, 6)));
+        let (slots, _) = allocate(&f, &ranges);
+        assert_ne!(slots[1], slots[2]);
+    }
+
+    #[test]
+    fn locals_set_in_a_branch_read_as_zero_when_it_is_not_taken() {
+        let source = "extern console_log(x)
+pub fn f(c){ a=(c+5) console_log(a) if c { b=(c+0.5) } b }";
+        for level in crate::testing::OPT_LEVELS {
+            let wasm = crate::testing::compile_at(source, *level);
+            assert_eq!(
+                crate::testing::run(&wasm, "f", &[0.0]),
+                (0.0, vec![5.0]),
+                "{:?}",
+                level
+            );
+            assert_eq!(
+                crate::testing::run(&wasm, "f", &[1.0]).0,
+                1.5,
+                "{:?}",
+                level
+            );
+        }
+    }
+}
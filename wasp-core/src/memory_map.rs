@@ -0,0 +1,284 @@
+use crate::ir::*;
+use std::fmt;
+
+/// what is at an address of linear memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionKind {
+    /// kept unused so nothing has the address 0
+    Reserved,
+    Data(DataOrigin),
+    /// padding left to align what follows
+    Free,
+}
+
+pub struct Region {
+    pub offset: u32,
+    pub size: u32,
+    pub kind: RegionKind,
+    /// the text of a string literal or the name of a static
+    pub label: String,
+    /// uses the data of a region listed before at the same address
+    pub shared: bool,
+    /// the bytes of the region as they are in memory
+    pub bytes: Vec<u8>,
+}
+
+/// every address assigned before the heap
+pub struct MemoryMap {
+    /// in order of address
+    pub regions: Vec<Region>,
+    pub heap_start: u32,
+}
+
+fn describe_kind(kind: RegionKind) -> &'static str {
+    match kind {
+        RegionKind::Reserved => "reserved",
+        RegionKind::Data(DataOrigin::Text) => "string literal",
+        RegionKind::Data(DataOrigin::Static) => "static",
+        RegionKind::Data(DataOrigin::Struct) => "struct",
+        RegionKind::Free => "free",
+    }
+}
+
+/// the bytes the data segments of a module put at some addresses
+fn read(module: &Module, offset: u32, size: u32) -> Vec<u8> {
+    let mut bytes = vec![0; size as usize];
+    for d in module.data.iter() {
+        for (i, b) in d.bytes.iter().enumerate() {
+            let address = d.offset + i as u32;
+            if address >= offset && address < offset + size {
+                bytes[(address - offset) as usize] = *b;
+            }
+        }
+    }
+    bytes
+}
+
+/// where everything placed in memory by a module is
+pub fn memory_map(module: &Module) -> MemoryMap {
+    let mut placements = module.placements.iter().collect::<Vec<&Placement>>();
+    placements.sort_by_key(|p| p.offset);
+
+    let mut regions = vec![Region {
+        offset: 0,
+        size: crate::layout::DATA_START,
+        kind: RegionKind::Reserved,
+        label: String::new(),
+        shared: false,
+        bytes: vec![],
+    }];
+    let mut end = crate::layout::DATA_START;
+    for p in placements {
+        if p.offset > end {
+            regions.push(Region {
+                offset: end,
+                size: p.offset - end,
+                kind: RegionKind::Free,
+                label: String::new(),
+                shared: false,
+                bytes: vec![],
+            });
+        }
+        regions.push(Region {
+            offset: p.offset,
+            size: p.size,
+            kind: RegionKind::Data(p.origin),
+            label: p.label.clone(),
+            shared: p.shared,
+            bytes: read(module, p.offset, p.size),
+        });
+        end = end.max(p.offset + p.size);
+    }
+    if module.heap_start > end {
+        regions.push(Region {
+            offset: end,
+            size: module.heap_start - end,
+            kind: RegionKind::Free,
+            label: String::new(),
+            shared: false,
+            bytes: vec![],
+        });
+    }
+    MemoryMap {
+        regions,
+        heap_start: module.heap_start,
+    }
+}
+
+impl Region {
+    /// the numbers of static data and struct member tables
+    pub fn numbers(&self) -> Vec<f64> {
+        match self.kind {
+            RegionKind::Data(DataOrigin::Static) | RegionKind::Data(DataOrigin::Struct) => self
+                .bytes
+                .chunks_exact(8)
+                .map(|b| {
+                    let mut n = [0; 8];
+                    n.copy_from_slice(b);
+                    f64::from_le_bytes(n)
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// the text of a string literal, without the 0 ending it
+    pub fn text(&self) -> Option<String> {
+        match self.kind {
+            RegionKind::Data(DataOrigin::Text) => {
+                let end = self.bytes.iter().position(|b| *b == 0);
+                let bytes = &self.bytes[..end.unwrap_or(self.bytes.len())];
+                Some(String::from_utf8_lossy(bytes).to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_number(n: f64) -> String {
+    if n.is_finite() {
+        format!("{}", n)
+    } else {
+        "null".to_string()
+    }
+}
+
+impl MemoryMap {
+    /// the map as a JSON object with a list of regions and the heap start
+    pub fn to_json(&self) -> String {
+        let regions = self
+            .regions
+            .iter()
+            .map(|r| {
+                let mut fields = vec![
+                    format!("\"address\":{}", r.offset),
+                    format!("\"size\":{}", r.size),
+                    format!("\"kind\":{}", json_string(describe_kind(r.kind))),
+                ];
+                match r.text() {
+                    Some(text) => fields.push(format!("\"text\":{}", json_string(&text))),
+                    None if !r.label.is_empty() => {
+                        fields.push(format!("\"name\":{}", json_string(&r.label)))
+                    }
+                    None => {}
+                }
+                if let RegionKind::Data(DataOrigin::Static) | RegionKind::Data(DataOrigin::Struct) =
+                    r.kind
+                {
+                    let numbers = r.numbers().into_iter().map(json_number);
+                    fields.push(format!(
+                        "\"values\":[{}]",
+                        numbers.collect::<Vec<String>>().join(",")
+                    ));
+                }
+                if r.shared {
+                    fields.push("\"shared\":true".to_string());
+                }
+                format!("{{{}}}", fields.join(","))
+            })
+            .collect::<Vec<String>>();
+        format!(
+            "{{\"regions\":[{}],\"heap_start\":{}}}",
+            regions.join(","),
+            self.heap_start
+        )
+    }
+}
+
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>8}  {:>6}  {:<14}  contents",
+            "address", "bytes", "kind"
+        )?;
+        for r in self.regions.iter() {
+            let mut contents = r.label.clone();
+            let numbers = r.numbers();
+            if !numbers.is_empty() {
+                let numbers = numbers
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<String>>();
+                contents = format!("{} ({})", contents, numbers.join(", "));
+            }
+            if r.shared {
+                contents = format!("{} (shared)", contents);
+            }
+            let line = format!(
+                "{:>8}  {:>6}  {:<14}  {}",
+                r.offset,
+                r.size,
+                describe_kind(r.kind),
+                contents
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        write!(f, "{:>8}  {:>6}  heap start", self.heap_start, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{OptLevel, Options};
+
+    fn map(source: &str) -> MemoryMap {
+        let app = crate::parser::parse(source).expect("source parses");
+        let options = Options {
+            opt_level: OptLevel::MinSize,
+            ..Default::default()
+        };
+        let output = crate::compiler::compile_with_options(app, &options).expect("source compiles");
+        memory_map(&output.module)
+    }
+
+    const SOURCE: &str = "static numbers = (1.5, 2)
+pub fn main() { a = \"a\tb\" b = \"a\tb\" ((mem(numbers) + a) + b) }";
+
+    #[test]
+    fn every_address_before_the_heap_is_mapped() {
+        let expected = " address   bytes  kind            contents
+       0       4  reserved
+       4       4  free
+       8      16  static          numbers (1.5, 2)
+      24       4  string literal  \"a\\tb\"
+      24       4  string literal  \"a\\tb\" (shared)
+      28       4  free
+      32          heap start";
+        assert_eq!(map(SOURCE).to_string(), expected);
+    }
+
+    #[test]
+    fn maps_are_given_as_json() {
+        let expected = concat!(
+            "{\"regions\":[",
+            "{\"address\":0,\"size\":4,\"kind\":\"reserved\"},",
+            "{\"address\":4,\"size\":4,\"kind\":\"free\"},",
+            "{\"address\":8,\"size\":16,\"kind\":\"static\",\"name\":\"numbers\",\"values\":[1.5,2]},",
+            "{\"address\":24,\"size\":4,\"kind\":\"string literal\",\"text\":\"a\\tb\"},",
+            "{\"address\":24,\"size\":4,\"kind\":\"string literal\",\"text\":\"a\\tb\",\"shared\":true},",
+            "{\"address\":28,\"size\":4,\"kind\":\"free\"}",
+            "],\"heap_start\":32}"
+        );
+        assert_eq!(map(SOURCE).to_json(), expected);
+        assert_eq!(json_number(f64::NAN), "null");
+    }
+}
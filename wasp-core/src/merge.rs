@@ -0,0 +1,76 @@
+use crate::ir::*;
+use std::collections::HashMap;
+
+/// the function standing in for a function, following functions merged
+/// into others that were merged later
+fn resolve(canonical: &[u32], mut f: u32) -> u32 {
+    while canonical[f as usize] != f {
+        f = canonical[f as usize];
+    }
+    f
+}
+
+/// a function's body with the calls it makes going to the functions that
+/// stand in for the ones called
+fn normalized(f: &Function, canonical: &[u32]) -> Vec<Expr> {
+    let mut body = f.body.clone();
+    for x in body.iter_mut() {
+        walk_mut(x, 0, &mut |x, _| {
+            if let Expr::Call(g, _) = x {
+                *g = resolve(canonical, *g);
+            }
+        });
+    }
+    body
+}
+
+/// functions that compile to exactly the same thing as another function,
+/// mapped to the name of the function used in their place. Exported
+/// functions are kept as they are since the host calls them by name.
+pub fn identical_functions(module: &Module) -> HashMap<String, String> {
+    let imports = module.imports.len();
+    let mut canonical = (0..(imports + module.functions.len()) as u32).collect::<Vec<u32>>();
+    // merging functions can make the functions calling them identical too
+    loop {
+        let bodies = module
+            .functions
+            .iter()
+            .map(|f| normalized(f, &canonical))
+            .collect::<Vec<Vec<Expr>>>();
+        let mut changed = false;
+        for (i, f) in module.functions.iter().enumerate() {
+            let idx = imports + i;
+            if f.export.is_some() || canonical[idx] != idx as u32 {
+                continue;
+            }
+            let same = module.functions.iter().enumerate().position(|(j, g)| {
+                canonical[imports + j] == (imports + j) as u32
+                    && j != i
+                    && (g.export.is_some() || j < i)
+                    && g.signature == f.signature
+                    && g.locals == f.locals
+                    && bodies[j] == bodies[i]
+            });
+            if let Some(j) = same {
+                canonical[idx] = (imports + j) as u32;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    canonical
+        .iter()
+        .enumerate()
+        .skip(imports)
+        .filter(|(i, c)| *i as u32 != **c)
+        .map(|(i, _)| {
+            let c = resolve(&canonical, i as u32) as usize;
+            (
+                module.functions[i - imports].name.clone(),
+                module.functions[c - imports].name.clone(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,613 @@
+use crate::ast::*;
+use failure::Error;
+use std::collections::HashMap;
+
+/// a single wasp source file and its place in the module tree
+#[derive(Debug)]
+pub struct Module {
+    /// path of the module, e.g. `["std", "list"]` for `vendor/std/list.w`
+    pub path: Vec<String>,
+    /// modules of the package being built, whose warnings are reported
+    pub root: bool,
+    /// the module the package is built from, its public functions are the
+    /// ones exported to the host
+    pub entry: bool,
+    pub app: App,
+}
+
+/// functions built into the compiler that are never resolved against modules
+const BUILTIN_FUNCTIONS: &[&str] = &[
+    "assert",
+    "call",
+    "mem",
+    "mem_byte",
+    "mem_heap_start",
+    "mem_heap_end",
+    "==",
+    "!=",
+    "<=",
+    ">=",
+    "<",
+    ">",
+    "&",
+    "|",
+    "^",
+    "<<",
+    ">>",
+    "+",
+    "-",
+    "*",
+    "/",
+    "%",
+    "!",
+    "~",
+    "and",
+    "or",
+];
+
+/// identifiers built into the compiler
+const BUILTIN_IDENTIFIERS: &[&str] = &["nil", "size_num"];
+
+pub fn is_builtin_function(name: &str) -> bool {
+    BUILTIN_FUNCTIONS.contains(&name)
+}
+
+pub fn is_builtin_identifier(name: &str) -> bool {
+    BUILTIN_IDENTIFIERS.contains(&name)
+}
+
+/// the name a definition is known by to the host, e.g. `cons` for `std::list::cons`
+pub fn unqualified_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+struct Definition {
+    public: bool,
+    /// name used for this definition once all modules are linked together
+    linked_name: String,
+}
+
+struct Linker {
+    paths: Vec<Vec<String>>,
+    definitions: Vec<HashMap<String, Definition>>,
+    imports: Vec<HashMap<String, String>>,
+    errors: Vec<String>,
+}
+
+impl Linker {
+    fn module_name(&self, m: usize) -> String {
+        self.paths[m].join("::")
+    }
+
+    /// can a module use a definition of another
+    fn is_visible(&self, from: usize, m: usize, name: &str) -> bool {
+        match self.definitions[m].get(name) {
+            Some(d) => d.public || m == from,
+            None => false,
+        }
+    }
+
+    /// module paths may also be written relative to the package they are used
+    /// in, when a dependency has a module of the same path only one of them
+    /// may define the name
+    fn expand_path(&self, from: usize, path: &[String], name: &str) -> Result<Vec<String>, String> {
+        let mut local_path = vec![self.paths[from][0].clone()];
+        local_path.extend_from_slice(path);
+        let local = self.paths.iter().position(|p| *p == local_path);
+        let absolute = self.paths.iter().position(|p| p.as_slice() == path);
+        match (local, absolute) {
+            (Some(l), Some(a)) if self.is_visible(from, a, name) => {
+                if self.is_visible(from, l, name) {
+                    Err(format!(
+                        "{}::{} is ambiguous, it could be any of {}",
+                        path.join("::"),
+                        name,
+                        self.describe_candidates(&[l, a], name)
+                    ))
+                } else {
+                    Ok(path.to_vec())
+                }
+            }
+            (Some(_), _) => Ok(local_path),
+            _ => Ok(path.to_vec()),
+        }
+    }
+
+    /// find a public definition with a qualified name such as `std::list::cons` or `std::cons`
+    fn resolve_qualified(
+        &self,
+        from: usize,
+        path: &[String],
+        name: &str,
+    ) -> Result<String, String> {
+        let path = &self.expand_path(from, path, name)?[..];
+        let qualified = format!("{}::{}", path.join("::"), name);
+        // an exact module match wins over searching the whole package
+        for m in 0..self.paths.len() {
+            if self.paths[m].as_slice() == path {
+                return match self.definitions[m].get(name) {
+                    Some(d) if d.public || m == from => Ok(d.linked_name.clone()),
+                    Some(_) => Err(format!(
+                        "{} is private to module {}",
+                        qualified,
+                        self.module_name(m)
+                    )),
+                    None => Err(format!(
+                        "module {} has no definition {}",
+                        path.join("::"),
+                        name
+                    )),
+                };
+            }
+        }
+        let candidates = (0..self.paths.len())
+            .filter(|m| self.paths[*m].starts_with(path))
+            .filter(|m| match self.definitions[*m].get(name) {
+                Some(d) => d.public,
+                _ => false,
+            })
+            .collect::<Vec<usize>>();
+        match candidates.len() {
+            0 => Err(format!("{} could not be found", qualified)),
+            1 => Ok(self.definitions[candidates[0]][name].linked_name.clone()),
+            _ => Err(format!(
+                "{} is ambiguous, it could be any of {}",
+                qualified,
+                self.describe_candidates(&candidates, name)
+            )),
+        }
+    }
+
+    fn describe_candidates(&self, candidates: &[usize], name: &str) -> String {
+        candidates
+            .iter()
+            .map(|m| format!("{}::{}", self.module_name(*m), name))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// resolve a name used at a location within a module to the name of its
+    /// definition, None means the name is unknown and is left for the
+    /// compiler to report
+    fn resolve(&mut self, from: usize, name: &str, location: &Location) -> Option<String> {
+        if name.contains("::") {
+            let mut path = name
+                .split("::")
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>();
+            let last = path.pop().unwrap();
+            return match self.resolve_qualified(from, &path, &last) {
+                Ok(n) => Some(n),
+                Err(e) => {
+                    self.errors.push(format!("{}: {}", location, e));
+                    None
+                }
+            };
+        }
+        if let Some(d) = self.definitions[from].get(name) {
+            return Some(d.linked_name.clone());
+        }
+        if let Some(n) = self.imports[from].get(name) {
+            return Some(n.clone());
+        }
+        // anything public can still be used unqualified as long as it is unambiguous
+        let candidates = (0..self.paths.len())
+            .filter(|m| match self.definitions[*m].get(name) {
+                Some(d) => d.public,
+                _ => false,
+            })
+            .collect::<Vec<usize>>();
+        match candidates.len() {
+            0 => None,
+            1 => Some(self.definitions[candidates[0]][name].linked_name.clone()),
+            _ => {
+                let e = format!(
+                    "{}: {} is ambiguous, it could be any of {}; add a use or qualify the name",
+                    location,
+                    name,
+                    self.describe_candidates(&candidates, name)
+                );
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+
+    fn resolve_global_value(&mut self, from: usize, v: &mut GlobalValue, location: &Location) {
+        match v {
+            GlobalValue::Identifier(name) if !is_builtin_identifier(name) => {
+                if let Some(n) = self.resolve(from, name, location) {
+                    *name = n;
+                }
+            }
+            GlobalValue::Data(values) => {
+                for v in values.iter_mut() {
+                    self.resolve_global_value(from, v, location);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve_expressions(&mut self, from: usize, locals: &mut Vec<String>, e: &mut [Expression]) {
+        for x in e.iter_mut() {
+            self.resolve_expression(from, locals, x);
+        }
+    }
+
+    fn resolve_expression(&mut self, from: usize, locals: &mut Vec<String>, e: &mut Expression) {
+        match e {
+            Expression::Identifier(x) => {
+                if !locals.contains(&x.name) && !is_builtin_identifier(&x.name) {
+                    if let Some(n) = self.resolve(from, &x.name, &x.location) {
+                        x.name = n;
+                    }
+                }
+            }
+            Expression::FunctionCall(x) => {
+                if !is_builtin_function(&x.function_name) {
+                    if let Some(n) = self.resolve(from, &x.function_name, &x.location) {
+                        x.function_name = n;
+                    }
+                }
+                self.resolve_expressions(from, locals, &mut x.params);
+            }
+            Expression::Assignment(x) => {
+                self.resolve_expression(from, locals, &mut x.value);
+                // assignments always bind a local, even if a global has the same name
+                locals.push(x.id.clone());
+            }
+            Expression::IfStatement(x) => {
+                self.resolve_expression(from, locals, &mut x.condition);
+                self.resolve_expressions(from, locals, &mut x.if_true);
+                if let Some(f) = &mut x.if_false {
+                    self.resolve_expressions(from, locals, f);
+                }
+            }
+            Expression::Loop(x) => self.resolve_expressions(from, locals, &mut x.expressions),
+            Expression::TextLiteral(_)
+            | Expression::SymbolLiteral(_)
+            | Expression::Number(_)
+            | Expression::Recur(_)
+            | Expression::FnSig(_) => {}
+        }
+    }
+}
+
+/// dependencies aren't something the package being built can fix, so they never warn
+fn allow_warnings(location: &Location) -> Attribute {
+    Attribute {
+        name: "allow".to_string(),
+        args: vec![crate::lint::WARNINGS.to_string()],
+        location: location.clone(),
+    }
+}
+
+/// combine modules into a single app, giving every definition a unique
+/// qualified name and resolving names through uses and module paths
+pub fn link(modules: Vec<Module>) -> Result<App, Error> {
+    let mut linker = Linker {
+        paths: modules.iter().map(|m| m.path.clone()).collect(),
+        definitions: vec![],
+        imports: vec![],
+        errors: vec![],
+    };
+
+    // externs share one namespace on the host so identical declarations share one import
+    let mut extern_names: HashMap<String, (String, usize, Location)> = HashMap::new();
+    for m in modules.iter() {
+        let prefix = m.path.join("::");
+        let mut definitions = HashMap::new();
+        for op in m.app.children.iter() {
+            let (name, public, linked_name, location) = match op {
+                // overrides take the name of what they replace so they don't define one
+                TopLevelOperation::DefineFunction(f) if f.overrides => continue,
+                TopLevelOperation::DefineGlobal(g) if g.overrides => continue,
+                TopLevelOperation::DefineFunction(f) => (
+                    &f.name,
+                    f.exported,
+                    format!("{}::{}", prefix, f.name),
+                    &f.location,
+                ),
+                TopLevelOperation::DefineGlobal(g) => (
+                    &g.name,
+                    g.public,
+                    format!("{}::{}", prefix, g.name),
+                    &g.location,
+                ),
+                TopLevelOperation::ExternalFunction(f) => {
+                    let linked_name = match extern_names.get(&f.name) {
+                        Some((n, arity, _)) if *arity == f.params.len() => n.clone(),
+                        Some((_, _, first)) => {
+                            linker.errors.push(format!(
+                                "{}: extern {} is declared with a different number of parameters than elsewhere\n  {}: first declared here",
+                                f.location, f.name, first
+                            ));
+                            continue;
+                        }
+                        None => {
+                            let n = format!("{}::{}", prefix, f.name);
+                            extern_names.insert(
+                                f.name.clone(),
+                                (n.clone(), f.params.len(), f.location.clone()),
+                            );
+                            n
+                        }
+                    };
+                    (&f.name, f.public, linked_name, &f.location)
+                }
+                _ => continue,
+            };
+            if name.contains("::") {
+                linker.errors.push(format!(
+                    "{}: only overrides can define a qualified name like {}",
+                    location, name
+                ));
+            }
+            definitions.entry(name.clone()).or_insert(Definition {
+                public,
+                linked_name,
+            });
+        }
+        linker.definitions.push(definitions);
+    }
+
+    for (i, m) in modules.iter().enumerate() {
+        let mut imports = HashMap::new();
+        let mut imported_at: HashMap<&str, &Location> = HashMap::new();
+        for op in m.app.children.iter() {
+            if let TopLevelOperation::Use(u) = op {
+                for name in u.names.iter() {
+                    let n = match linker.resolve_qualified(i, &u.path, name) {
+                        Ok(n) => n,
+                        Err(e) => {
+                            linker.errors.push(format!("{}: {}", u.location, e));
+                            continue;
+                        }
+                    };
+                    // a name can only stand for one definition in a module
+                    if linker.definitions[i].contains_key(name) {
+                        linker.errors.push(format!(
+                            "{}: use of {} conflicts with the definition of the same name in this module",
+                            u.location, n
+                        ));
+                    } else if let Some(first) = imported_at.get(name.as_str()) {
+                        if imports.get(name) != Some(&n) {
+                            linker.errors.push(format!(
+                                "{}: {} is already used from elsewhere, qualify one of them instead\n  {}: first used here",
+                                u.location, name, first
+                            ));
+                        }
+                    } else {
+                        imported_at.insert(name, &u.location);
+                        imports.insert(name.clone(), n);
+                    }
+                }
+            }
+        }
+        linker.imports.push(imports);
+    }
+
+    let mut children = vec![];
+    let mut emitted_externs = vec![];
+    for (i, m) in modules.into_iter().enumerate() {
+        let prefix = m.path.join("::");
+        for op in m.app.children.into_iter() {
+            match op {
+                TopLevelOperation::DefineFunction(mut f) => {
+                    let mut locals = f.params.clone();
+                    linker.resolve_expressions(i, &mut locals, &mut f.children);
+                    f.name = if f.overrides {
+                        match linker.resolve(i, &f.name, &f.location) {
+                            Some(n) => n,
+                            None => f.name,
+                        }
+                    } else {
+                        format!("{}::{}", prefix, f.name)
+                    };
+                    // only the entry module decides what the host sees, so
+                    // other modules can share names of public functions
+                    f.exported = f.exported && m.entry;
+                    if !m.root {
+                        f.attributes.push(allow_warnings(&f.location));
+                    }
+                    children.push(TopLevelOperation::DefineFunction(f));
+                }
+                TopLevelOperation::DefineGlobal(mut g) => {
+                    linker.resolve_global_value(i, &mut g.value, &g.location);
+                    g.name = if g.overrides {
+                        match linker.resolve(i, &g.name, &g.location) {
+                            Some(n) => n,
+                            None => g.name,
+                        }
+                    } else {
+                        format!("{}::{}", prefix, g.name)
+                    };
+                    children.push(TopLevelOperation::DefineGlobal(g));
+                }
+                TopLevelOperation::ExternalFunction(mut f) => {
+                    let linked_name = match linker.definitions[i].get(&f.name) {
+                        Some(d) => d.linked_name.clone(),
+                        None => continue,
+                    };
+                    if !emitted_externs.contains(&linked_name) {
+                        emitted_externs.push(linked_name.clone());
+                        f.name = linked_name;
+                        if !m.root {
+                            f.attributes.push(allow_warnings(&f.location));
+                        }
+                        children.push(TopLevelOperation::ExternalFunction(f));
+                    }
+                }
+                TopLevelOperation::Use(_) => {}
+                TopLevelOperation::Comment(c) => children.push(TopLevelOperation::Comment(c)),
+            }
+        }
+    }
+
+    if !linker.errors.is_empty() {
+        return Err(format_err!("{}", linker.errors.join("\n")));
+    }
+    Ok(App { children })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(file: &str, source: &str, entry: bool) -> Module {
+        let mut path = vec!["myproject".to_string()];
+        path.extend(
+            file.trim_end_matches(".w")
+                .split('/')
+                .map(|s| s.to_string()),
+        );
+        Module {
+            path,
+            root: true,
+            entry,
+            app: crate::parser::parse_file(file, source).expect("source parses"),
+        }
+    }
+
+    fn exported(app: &App) -> Vec<&str> {
+        app.children
+            .iter()
+            .filter_map(|op| match op {
+                TopLevelOperation::DefineFunction(f) if f.exported => Some(f.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_the_entry_module_exports_its_public_functions() {
+        let app = link(vec![
+            module(
+                "main.w",
+                "pub fn main() { (a::helper() + b::helper()) }",
+                true,
+            ),
+            module("a.w", "pub fn helper() { 1 }", false),
+            module("b.w", "pub fn helper() { 2 }", false),
+        ])
+        .unwrap();
+        assert_eq!(exported(&app), vec!["myproject::main::main"]);
+        let wasm = crate::compiler::compile(app).unwrap();
+        assert_eq!(crate::testing::run(&wasm, "main", &[]).0, 3.0);
+    }
+
+    #[test]
+    fn linker_errors_give_where_they_happened() {
+        let error = |main: &str| {
+            link(vec![
+                module("main.w", main, true),
+                module("a.w", "pub fn helper() { 1 }\nfn hidden() { 2 }", false),
+                module("b.w", "pub fn helper() { 2 }", false),
+            ])
+            .unwrap_err()
+            .to_string()
+        };
+        assert!(
+            error("pub fn main() {\n  helper()\n}").starts_with("main.w:2:3: helper is ambiguous")
+        );
+        assert!(error("\nuse myproject::a::missing\npub fn main() { 1 }")
+            .starts_with("main.w:2:1: module myproject::a has no definition missing"));
+        assert!(error("pub fn main() { a::hidden() }")
+            .starts_with("main.w:1:17: myproject::a::hidden is private"));
+    }
+
+    /// a module of a dependency such as `vendor/std/list.w`
+    fn dependency(path: &str, source: &str) -> Module {
+        Module {
+            path: path.split("::").map(|s| s.to_string()).collect(),
+            root: false,
+            entry: false,
+            app: crate::parser::parse_file(&format!("{}.w", path), source).expect("source parses"),
+        }
+    }
+
+    #[test]
+    fn names_that_could_be_several_definitions_are_errors() {
+        let error = |main: &str| {
+            link(vec![
+                module("main.w", main, true),
+                module(
+                    "std/list.w",
+                    "pub fn cons(h, t) { 1 }\npub fn local() { 1 }",
+                    false,
+                ),
+                dependency("std::list", "pub fn cons(h, t) { 2 }\npub fn head(l) { 2 }"),
+                dependency("std::map", "pub fn cons(h, t) { 3 }"),
+            ])
+            .map(|app| exported(&app).len())
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            error("pub fn main() { cons(1, 2) }").unwrap_err(),
+            "main.w:1:17: cons is ambiguous, it could be any of myproject::std::list::cons, std::list::cons, std::map::cons; add a use or qualify the name"
+        );
+        // searching a package for a definition
+        assert_eq!(
+            error("pub fn main() { std::cons(1, 2) }").unwrap_err(),
+            "main.w:1:17: std::cons is ambiguous, it could be any of std::list::cons, std::map::cons"
+        );
+        // a module of the package and one of a dependency with the same path
+        assert_eq!(
+            error("use std::list::{cons}\npub fn main() { cons(1, 2) }").unwrap_err().lines().next(),
+            Some("main.w:1:1: std::list::cons is ambiguous, it could be any of myproject::std::list::cons, std::list::cons")
+        );
+        assert_eq!(
+            error("pub fn main() { (std::list::head(1) + std::list::local()) }"),
+            Ok(1)
+        );
+        assert_eq!(
+            error("pub fn main() { myproject::std::list::cons(1, 2) }"),
+            Ok(1)
+        );
+        assert_eq!(
+            error("use std::map::{cons}\npub fn main() { cons(1, 2) }"),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn uses_stand_for_one_definition() {
+        let error = |main: &str| {
+            link(vec![
+                module("main.w", main, true),
+                module("a.w", "pub fn helper() { 1 }", false),
+                module("b.w", "pub fn helper() { 2 }", false),
+            ])
+            .unwrap_err()
+            .to_string()
+        };
+        assert_eq!(
+            error("use a::{helper}\nuse b::{helper}\npub fn main() { helper() }"),
+            "main.w:2:1: helper is already used from elsewhere, qualify one of them instead\n  main.w:1:1: first used here"
+        );
+        assert_eq!(
+            error("use a::{helper}\nfn helper() { 3 }\npub fn main() { helper() }"),
+            "main.w:1:1: use of myproject::a::helper conflicts with the definition of the same name in this module"
+        );
+    }
+
+    #[test]
+    fn only_public_definitions_of_dependencies_can_be_used() {
+        let result = |main: &str| {
+            link(vec![
+                module("main.w", main, true),
+                dependency("std::list", "pub fn cons(h, t) { h }\nfn helper() { 2 }"),
+            ])
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(result("pub fn main() { std::list::cons(1, 2) }"), Ok(()));
+        assert_eq!(
+            result("pub fn main() { std::list::helper() }").unwrap_err(),
+            "main.w:1:17: std::list::helper is private to module std::list"
+        );
+        // unqualified names never reach private definitions of other modules
+        assert_eq!(result("pub fn main() { helper() }"), Ok(()));
+    }
+}
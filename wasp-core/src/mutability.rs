@@ -0,0 +1,224 @@
+use crate::ast::*;
+use failure::Error;
+use std::collections::HashMap;
+
+struct Binding<'a> {
+    name: &'a str,
+    mutable: bool,
+    parameter: bool,
+    /// how many loops deep the binding was made
+    loop_depth: usize,
+    /// the arms of ifs the binding was made in
+    arms: &'a [Arm],
+    location: &'a Location,
+}
+
+/// an arm of an if, numbered in the order ifs are visited
+#[derive(Clone, Copy, PartialEq)]
+struct Arm {
+    id: usize,
+    if_true: bool,
+}
+
+/// were two bindings made in different arms of the same if, so only one of
+/// them ever happens
+fn are_exclusive(a: &[Arm], b: &[Arm]) -> bool {
+    a.iter()
+        .any(|x| b.iter().any(|y| x.id == y.id && x.if_true != y.if_true))
+}
+
+/// the arms of ifs every assignment within some expressions is made in,
+/// outermost first
+fn arms(e: &[Expression]) -> HashMap<*const OperationAssignment, Vec<Arm>> {
+    let mut arms: HashMap<*const OperationAssignment, Vec<Arm>> = HashMap::new();
+    let mut ifs = 0;
+    for x in e.iter() {
+        walk(x, 0, &mut |x, _| {
+            if let Expression::IfStatement(x) = x {
+                let branches = x.if_true.iter().map(|y| (true, y));
+                let branches = branches.chain(x.if_false.iter().flatten().map(|y| (false, y)));
+                for (if_true, y) in branches {
+                    walk(y, 0, &mut |y, _| {
+                        if let Expression::Assignment(a) = y {
+                            let arm = Arm { id: ifs, if_true };
+                            arms.entry(a as *const _).or_default().push(arm);
+                        }
+                    });
+                }
+                ifs += 1;
+            }
+        });
+    }
+    arms
+}
+
+struct MutabilityChecker<'a> {
+    bindings: Vec<Binding<'a>>,
+    /// for each loop we are in, whether it can recur
+    loops: Vec<bool>,
+    errors: Vec<String>,
+}
+
+/// does a loop restart itself, recurs of nested loops restart those instead
+fn recurs(e: &[Expression]) -> bool {
+    let mut found = false;
+    for x in e.iter() {
+        walk(x, 0, &mut |x, loops| {
+            found |= loops == 0 && matches!(x, Expression::Recur(_));
+        });
+    }
+    found
+}
+
+impl<'a> MutabilityChecker<'a> {
+    /// rebinding in a loop that recurs is how values change from one iteration to the next
+    fn is_loop_rebinding(&self, binding_depth: usize) -> bool {
+        self.loops.iter().skip(binding_depth).any(|recurs| *recurs)
+    }
+
+    fn check_assignment(&mut self, x: &'a OperationAssignment, arms: &'a [Arm]) {
+        let existing = self
+            .bindings
+            .iter()
+            .find(|b| b.name == x.id && !are_exclusive(b.arms, arms));
+        match existing {
+            None => self.bindings.push(Binding {
+                name: &x.id,
+                mutable: x.mutable,
+                parameter: false,
+                loop_depth: self.loops.len(),
+                arms,
+                location: &x.location,
+            }),
+            Some(b) if x.mutable => self.errors.push(format!(
+                "{}: {} is already bound, remove mut to assign to it\n  {}: first bound here",
+                x.location, x.id, b.location
+            )),
+            Some(b) if b.mutable || self.is_loop_rebinding(b.loop_depth) => {}
+            Some(b) if b.parameter => self.errors.push(format!(
+                "{}: cannot assign to parameter {}, bind the new value to a `mut` local instead\n  {}: parameter of this function",
+                x.location, x.id, b.location
+            )),
+            Some(b) => self.errors.push(format!(
+                "{}: cannot assign twice to immutable {}\n  {}: first bound here, declare it with `mut {}` to make it mutable",
+                x.location, x.id, b.location, x.id
+            )),
+        }
+    }
+
+    fn check_function(
+        &mut self,
+        f: &'a FunctionDefinition,
+        arms: &'a HashMap<*const OperationAssignment, Vec<Arm>>,
+    ) {
+        for x in f.children.iter() {
+            walk(x, 0, &mut |x, loops| {
+                // leaving a loop is only noticed at what comes after it
+                self.loops.truncate(loops);
+                match x {
+                    Expression::Loop(x) => self.loops.push(recurs(&x.expressions)),
+                    Expression::Assignment(a) => {
+                        let arms = arms.get(&(a as *const _)).map(|a| &a[..]).unwrap_or(&[]);
+                        self.check_assignment(a, arms);
+                    }
+                    _ => {}
+                }
+            });
+        }
+        self.loops.clear();
+    }
+}
+
+/// make sure locals are only assigned again when declared with `mut`, or
+/// when rebound to give a loop that recurs its next values
+pub fn check_mutability(app: &App) -> Result<(), Error> {
+    let mut checker = MutabilityChecker {
+        bindings: vec![],
+        loops: vec![],
+        errors: vec![],
+    };
+    let arms = app
+        .children
+        .iter()
+        .map(|op| match op {
+            TopLevelOperation::DefineFunction(f) => arms(&f.children),
+            _ => HashMap::new(),
+        })
+        .collect::<Vec<_>>();
+    for (op, arms) in app.children.iter().zip(arms.iter()) {
+        if let TopLevelOperation::DefineFunction(f) = op {
+            checker.bindings = f
+                .params
+                .iter()
+                .map(|p| Binding {
+                    name: p,
+                    mutable: false,
+                    parameter: true,
+                    loop_depth: 0,
+                    arms: &[],
+                    location: &f.location,
+                })
+                .collect();
+            checker.check_function(f, arms);
+        }
+    }
+    if !checker.errors.is_empty() {
+        return Err(format_err!("{}", checker.errors.join("\n")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: &str) -> Result<(), String> {
+        let app = crate::parser::parse(source).expect("source parses");
+        check_mutability(&app).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn locals_bound_in_either_arm_of_an_if_are_bound_once() {
+        assert!(check("fn f(c) { if c { x = 1 } else { x = 2 } x }").is_ok());
+        let source = "pub fn f(c) { if c { x = 1 } else { x = 2 } x }";
+        for level in crate::testing::OPT_LEVELS {
+            let wasm = crate::testing::compile_at(source, *level);
+            assert_eq!(
+                crate::testing::run(&wasm, "f", &[1.0]).0,
+                1.0,
+                "{:?}",
+                level
+            );
+            assert_eq!(
+                crate::testing::run(&wasm, "f", &[0.0]).0,
+                2.0,
+                "{:?}",
+                level
+            );
+        }
+        assert!(
+            check("fn f(c, d) { if c { if d { x = 1 } else { x = 2 } } else { x = 3 } x }").is_ok()
+        );
+    }
+
+    #[test]
+    fn assigning_an_immutable_local_again_is_an_error() {
+        let error = check("fn f(c) {\n  x = 1\n  x = 2\n  x\n}").unwrap_err();
+        assert_eq!(
+            error,
+            "3:3: cannot assign twice to immutable x\n  2:3: first bound here, declare it with `mut x` to make it mutable"
+        );
+        // an arm of an if can still happen after what came before it
+        assert!(check("fn f(c) { x = 1 if c { x = 2 } else { 0 } x }").is_err());
+        assert!(check("fn f(c) { if c { x = 1 } else { x = 2 } x = 3 x }").is_err());
+        assert!(check("fn f(c) { mut x = 1 if c { x = 2 } else { x = 3 } x }").is_ok());
+    }
+
+    #[test]
+    fn locals_are_rebound_by_loops_that_recur() {
+        let source = "fn f(n) { i = 0 loop { if (i < n) { i = (i + 1) recur } else { i } } }";
+        assert!(check(source).is_ok());
+        let source = "fn f(n) { i = 0 loop { i = (i + 1) i } }";
+        assert!(check(source).is_err());
+    }
+}
@@ -1,9 +1,44 @@
 use crate::ast::*;
 use failure::Error;
 use nom::types::CompleteStr;
+use nom::IResult;
+use std::cell::RefCell;
 use std::str;
 use wasmly::DataType;
 
+/// the file being parsed, used to give locations to what we parse
+struct Source {
+    file: String,
+    length: usize,
+    line_starts: Vec<usize>,
+}
+
+thread_local! {
+    static SOURCE: RefCell<Source> = RefCell::new(Source {
+        file: String::new(),
+        length: 0,
+        line_starts: vec![0],
+    });
+}
+
+/// get the location of the remaining input within the file being parsed
+fn location(input: CompleteStr) -> IResult<CompleteStr, Location> {
+    let location = SOURCE.with(|s| {
+        let s = s.borrow();
+        let offset = s.length - input.len();
+        let line = match s.line_starts.binary_search(&offset) {
+            Ok(l) => l,
+            Err(l) => l - 1,
+        };
+        Location {
+            file: s.file.clone(),
+            line: line + 1,
+            column: offset - s.line_starts[line] + 1,
+        }
+    });
+    Ok((input, location))
+}
+
 fn to_string(s: CompleteStr) -> String {
     s.to_string()
 }
@@ -21,13 +56,29 @@ fn is_text_char(c: char) -> bool {
 }
 
 fn is_digit(c: char) -> bool {
-    c.is_digit(10)
+    c.is_ascii_digit()
 }
 
 fn is_comment_char(c: char) -> bool {
     c != '\r' && c != '\n'
 }
 
+fn to_use_declaration(
+    mut path: Vec<String>,
+    names: Option<Vec<String>>,
+    location: Location,
+) -> TopLevelOperation {
+    let names = match names {
+        Some(n) => n,
+        None => vec![path.pop().unwrap()],
+    };
+    TopLevelOperation::Use(UseDeclaration {
+        path,
+        names,
+        location,
+    })
+}
+
 fn to_data_type(c: &str) -> DataType {
     match c {
         "i32" => DataType::I32,
@@ -55,6 +106,14 @@ named!(
     )
 );
 
+named!(
+    token_path<CompleteStr,String>,
+    do_parse!(
+        segments: separated_nonempty_list!(tag!("::"), token_identifier) >>
+        (segments.join("::"))
+    )
+);
+
 named!(
     operator_identifiers<CompleteStr,String>,
     do_parse!(
@@ -74,7 +133,7 @@ named!(
 named!(
     function_identifiers<CompleteStr,String>,
     do_parse!(
-        id: alt!(map!(tag!("assert"),to_string)|map!(tag!("call"),to_string)|token_identifier)>>
+        id: alt!(map!(tag!("assert"),to_string)|map!(tag!("call"),to_string)|token_path)>>
         (id)
     )
 );
@@ -82,11 +141,33 @@ named!(
 named!(
     token_data_type<CompleteStr,DataType>,
     do_parse!(
-        t: map!(alt!(tag!("()")|tag!("i32")|tag!("i64")|tag!("f32")|tag!("f64")), to_string) >>
+        t: map!(alt!(tag!("i32")|tag!("i64")|tag!("f32")|tag!("f64")), to_string) >>
         (to_data_type(&t))
     )
 );
 
+named!(
+    token_return_type<CompleteStr,Option<DataType>>,
+    alt!(value!(None, tag!("()"))|map!(token_data_type, Some))
+);
+
+named!(
+    token_param<CompleteStr,(String,DataType)>,
+    do_parse!(
+        name: token_identifier >>
+        data_type: opt!(preceded!(ws!(tag!(":")), token_data_type)) >>
+        ((name, data_type.unwrap_or(DataType::F64)))
+    )
+);
+
+named!(
+    token_output<CompleteStr,Option<DataType>>,
+    do_parse!(
+        output: opt!(preceded!(ws!(tag!("->")), token_return_type)) >>
+        (output.unwrap_or(Some(DataType::F64)))
+    )
+);
+
 named!(
     token_text<CompleteStr,String>,
     do_parse!(
@@ -146,14 +227,54 @@ named!(
     alt!(positive_number|negative_number)
 );
 
+named!(attribute<CompleteStr, Attribute>,
+  do_parse!(
+    location: location >>
+    tag!("#[") >>
+    name: ws!(token_identifier) >>
+    tag!("(") >>
+    args: ws!(separated_list!(tag!(","),ws!(token_identifier))) >>
+    tag!(")") >>
+    ws!(tag!("]")) >>
+    (Attribute{name,args,location})
+  )
+);
+
 named!(external_function<CompleteStr, TopLevelOperation>,
   do_parse!(
+    attributes: many0!(ws!(attribute)) >>
+    location: location >>
+    public: opt!(ws!(tag!("pub"))) >>
     ws!(tag!("extern"))   >>
     function_name: ws!(token_identifier) >>
     ws!(tag!("("))   >>
-    params: ws!(separated_list!(tag!(","),ws!(token_identifier))) >>
+    params: ws!(separated_list!(tag!(","),ws!(token_param))) >>
     ws!(tag!(")"))   >>
-    (TopLevelOperation::ExternalFunction(ExternalFunction{name:function_name,params:params}))
+    output: token_output >>
+    (TopLevelOperation::ExternalFunction(ExternalFunction{name:function_name,public:public.is_some(),
+    param_types: params.iter().map(|p| p.1.clone()).collect(),
+    params: params.into_iter().map(|p| p.0).collect(),
+    output,attributes,location}))
+  )
+);
+
+named!(use_names<CompleteStr, Vec<String>>,
+  do_parse!(
+    tag!("::") >>
+    ws!(tag!("{")) >>
+    names: ws!(separated_nonempty_list!(tag!(","),ws!(token_identifier))) >>
+    tag!("}") >>
+    (names)
+  )
+);
+
+named!(use_declaration<CompleteStr, TopLevelOperation>,
+  do_parse!(
+    location: location >>
+    ws!(tag!("use")) >>
+    path: separated_nonempty_list!(tag!("::"), token_identifier) >>
+    names: opt!(use_names) >>
+    (to_use_declaration(path, names, location))
   )
 );
 
@@ -173,8 +294,9 @@ named!(expression_literal_token<CompleteStr, Expression>,
 
 named!(expression_identifier<CompleteStr, Expression>,
     do_parse!(
-      text: ws!(token_identifier) >>
-      (Expression::Identifier(text))
+      location: location >>
+      name: ws!(token_path) >>
+      (Expression::Identifier(OperationIdentifier{name,location}))
     )
 );
 
@@ -214,14 +336,15 @@ named!(expression_loop<CompleteStr, Expression>,
     ws!(tag!("{"))   >>
     expressions: expression_list >>
     tag!("}")   >>
-    (Expression::Loop(OperationLoop{expressions:expressions}))
+    (Expression::Loop(OperationLoop{expressions}))
   )
 );
 
 named!(expression_recur<CompleteStr, Expression>,
   do_parse!(
+    location: location >>
     tag!("recur")   >>
-    (Expression::Recur(OperationRecur{}))
+    (Expression::Recur(OperationRecur{location}))
   )
 );
 
@@ -236,8 +359,8 @@ named!(expression_fnsig<CompleteStr, Expression>,
     ws!(tag!(")"))   >>
     ws!(tag!("->"))   >>
     many0!(ws!(token_comment)) >>
-    output: opt!(ws!(token_data_type)) >>
-    (Expression::FnSig(OperationFnSig{inputs:inputs, output:output}))
+    output: opt!(ws!(token_return_type)) >>
+    (Expression::FnSig(OperationFnSig{inputs, output: output.unwrap_or(None)}))
   )
 );
 
@@ -269,21 +392,24 @@ named!(function_params<CompleteStr, Vec<Expression>>,
 
 named!(expression_operator_call<CompleteStr, Expression>,
   do_parse!(
+    location: location >>
     tag!("(") >>
     expr_a: ws!(expression) >>
     function_name: ws!(operator_identifiers) >>
     expr_b: ws!(expression) >>
     tag!(")") >>
-    (Expression::FunctionCall(OperationFunctionCall{function_name:function_name,params:vec![expr_a,expr_b]}))
+    (Expression::FunctionCall(OperationFunctionCall{function_name,params:vec![expr_a,expr_b],location}))
   )
 );
 
 named!(expression_assignment<CompleteStr, Expression>,
   do_parse!(
+    location: location >>
+    mutable: opt!(terminated!(tag!("mut"), nom::multispace)) >>
     id: ws!(token_identifier) >>
     ws!(tag!("=")) >>
     expr: ws!(expression) >>
-    (Expression::Assignment(OperationAssignment{id:id,value:Box::new(expr)}))
+    (Expression::Assignment(OperationAssignment{id,mutable:mutable.is_some(),value:Box::new(expr),location}))
   )
 );
 
@@ -311,44 +437,56 @@ named!(expression_if_statement<CompleteStr, Expression>,
 
 named!(expression_unary_operator_call<CompleteStr, Expression>,
   do_parse!(
+    location: location >>
     function_name: ws!(unary_operator_identifiers) >>
     expr_a: ws!(expression) >>
-    (Expression::FunctionCall(OperationFunctionCall{function_name:function_name,params:vec![expr_a]}))
+    (Expression::FunctionCall(OperationFunctionCall{function_name,params:vec![expr_a],location}))
   )
 );
 
 named!(expression_function_call<CompleteStr, Expression>,
   do_parse!(
+    location: location >>
     function_name: ws!(function_identifiers) >>
     tag!("(")   >>
     params: ws!(function_params) >>
     ws!(tag!(")"))   >>
-    (Expression::FunctionCall(OperationFunctionCall{function_name:function_name,params:params}))
+    (Expression::FunctionCall(OperationFunctionCall{function_name,params,location}))
   )
 );
 
 named!(define_function<CompleteStr, TopLevelOperation>,
   do_parse!(
+    attributes: many0!(ws!(attribute)) >>
+    location: location >>
     external_name:opt!( ws!(tag!("pub"))) >>
+    overrides: opt!(ws!(tag!("override"))) >>
+    inline: opt!(ws!(tag!("inline"))) >>
     many0!(ws!(token_comment)) >>
     ws!(tag!("fn"))   >>
     many0!(ws!(token_comment)) >>
-    function_name: ws!(token_identifier) >>
+    function_name: ws!(token_path) >>
     many0!(ws!(token_comment)) >>
     ws!(tag!("("))   >>
     many0!(ws!(token_comment)) >>
-    params: ws!(separated_list!(tag!(","),ws!(token_identifier))) >>
+    params: ws!(separated_list!(tag!(","),ws!(token_param))) >>
     many0!(ws!(token_comment)) >>
     ws!(tag!(")"))   >>
+    output: token_output >>
     many0!(ws!(token_comment)) >>
     ws!(tag!("{"))   >>
     children: expression_list >>
     tag!("}")   >>
     (TopLevelOperation::DefineFunction(FunctionDefinition{name: function_name,
     exported: external_name.is_some(),
-    params: params,
-    output: None,
-    children: children}))
+    overrides: overrides.is_some(),
+    inline: inline.is_some(),
+    param_types: params.iter().map(|p| p.1.clone()).collect(),
+    params: params.into_iter().map(|p| p.0).collect(),
+    output,
+    children,
+    attributes,
+    location}))
   )
 );
 
@@ -356,12 +494,14 @@ named!(struct_pair<CompleteStr, StructMember>,
   do_parse!(
     name: token_symbol >>
     many0!(ws!(token_comment)) >>
-    (StructMember{name: name})
+    (StructMember{name})
   )
 );
 
 named!(define_struct<CompleteStr, TopLevelOperation>,
   do_parse!(
+    location: location >>
+    public: opt!(ws!(tag!("pub"))) >>
     ws!(tag!("struct"))   >>
     many0!(ws!(token_comment)) >>
     name: ws!(token_identifier) >>
@@ -371,8 +511,8 @@ named!(define_struct<CompleteStr, TopLevelOperation>,
     members: many0!(ws!(struct_pair)) >>
     many0!(ws!(token_comment)) >>
     tag!("}")   >>
-    (TopLevelOperation::DefineGlobal(Global{name:name,value:GlobalValue::Struct(StructDefinition{
-    members: members})}))
+    (TopLevelOperation::DefineGlobal(Global{name,public:public.is_some(),overrides:false,value:GlobalValue::Struct(StructDefinition{
+    members}),location}))
   )
 );
 
@@ -413,7 +553,7 @@ named!(global_bool_false<CompleteStr, GlobalValue>,
 
 named!(global_identifier<CompleteStr, GlobalValue>,
   do_parse!(
-    value: token_identifier >>
+    value: token_path >>
     (GlobalValue::Identifier(value))
   )
 );
@@ -436,11 +576,14 @@ named!(global_value<CompleteStr, GlobalValue>,
 
 named!(define_global<CompleteStr, TopLevelOperation>,
   do_parse!(
+    location: location >>
+    public: opt!(ws!(tag!("pub"))) >>
+    overrides: opt!(ws!(tag!("override"))) >>
     ws!(tag!("static"))   >>
-    name: ws!(token_identifier) >>
+    name: ws!(token_path) >>
     ws!(tag!("="))   >>
     value: global_value >>
-    (TopLevelOperation::DefineGlobal(Global{name: name,value:value}))
+    (TopLevelOperation::DefineGlobal(Global{name,public:public.is_some(),overrides:overrides.is_some(),value,location}))
   )
 );
 
@@ -454,13 +597,26 @@ named!(comment<CompleteStr, TopLevelOperation>,
 
 named!(app<CompleteStr, App>,
   do_parse!(
-    op: many0!(ws!(alt!(comment|external_function|define_function|define_struct|define_global))) >>
+    op: many0!(ws!(alt!(comment|use_declaration|external_function|define_function|define_struct|define_global))) >>
     eof!() >>
     (App{children:op})
   )
 );
 
 pub fn parse(content: &str) -> Result<App, Error> {
+    parse_file("", content)
+}
+
+/// parse the content of a file, the file name is used in locations
+pub fn parse_file(file: &str, content: &str) -> Result<App, Error> {
+    SOURCE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.file = file.to_string();
+        s.length = content.len();
+        s.line_starts = vec![0];
+        s.line_starts
+            .extend(content.match_indices('\n').map(|(i, _)| i + 1));
+    });
     let result = app(CompleteStr(content));
     match result {
         Ok((_, value)) => Ok(value),
@@ -0,0 +1,237 @@
+use crate::fold::is_pure;
+use crate::ir::*;
+use wasmly::DataType;
+
+/// converting from one type to the other and back gives the value back
+fn round_trips(from: &DataType, to: &DataType) -> bool {
+    matches!(
+        (from, to),
+        (DataType::I32, DataType::I64)
+            | (DataType::I32, DataType::F64)
+            | (DataType::F32, DataType::F64)
+    )
+}
+
+fn is_comparison(e: &Expr) -> bool {
+    matches!(e, Expr::Binary(op, _, _, _) if op.is_comparison())
+}
+
+fn take(e: &mut Expr) -> Expr {
+    std::mem::replace(e, Expr::Sequence(vec![]))
+}
+
+/// a shorter way to write an expression, if there is one
+fn simplify(e: &mut Expr) -> bool {
+    let simpler = match e {
+        // keep a value and throw it away is just setting it
+        Expr::Drop(v) => match &mut **v {
+            Expr::LocalTee(i, v) => Expr::LocalSet(*i, Box::new(take(v))),
+            // only the last of a sequence leaves a value
+            Expr::Sequence(items) if !items.is_empty() => {
+                let last = items.pop().expect("not empty");
+                if last.is_unreachable() {
+                    items.push(last);
+                } else if !is_pure(&last) {
+                    items.push(Expr::Drop(Box::new(last)));
+                }
+                take(v)
+            }
+            _ => return false,
+        },
+        Expr::Convert(from, to, v) => match &mut **v {
+            Expr::Convert(inner_from, inner_to, x)
+                if inner_to == from && inner_from == to && round_trips(to, from) =>
+            {
+                take(x)
+            }
+            _ => return false,
+        },
+        // comparisons already give 0 or 1
+        Expr::Eqz(DataType::I32, v) => match &mut **v {
+            Expr::Eqz(DataType::I32, x) if is_comparison(x) => take(x),
+            _ => return false,
+        },
+        Expr::Binary(BinaryOp::Ne, DataType::I32, a, b)
+            if is_comparison(a) && **b == Expr::Const(Value::I32(0)) =>
+        {
+            take(a)
+        }
+        // test the condition the other way around instead of negating it
+        Expr::If(_, condition, if_true, if_false) if !if_false.is_empty() => match &mut **condition
+        {
+            Expr::Eqz(DataType::I32, x) => {
+                **condition = take(x);
+                std::mem::swap(if_true, if_false);
+                return true;
+            }
+            _ => return false,
+        },
+        _ => return false,
+    };
+    *e = simpler;
+    true
+}
+
+/// shorten what follows one after another in a list of expressions
+fn simplify_list(e: &mut Vec<Expr>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < e.len() {
+        match (&e[i], e.get(i + 1)) {
+            // a value thrown away without effects
+            (Expr::Drop(v), _) if is_pure(v) => {
+                e.remove(i);
+                changed = true;
+                continue;
+            }
+            // set a local and then read it is keeping the value set
+            (Expr::LocalSet(n, _), Some(Expr::LocalGet(m))) if n == m => {
+                if let Expr::LocalSet(n, v) = e.remove(i) {
+                    e[i] = Expr::LocalTee(n, v);
+                }
+                changed = true;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    changed
+}
+
+fn optimize_list(e: &mut Vec<Expr>) -> bool {
+    let mut changed = simplify_list(e);
+    for x in e.iter_mut() {
+        walk_mut(x, 0, &mut |x, _| {
+            changed |= simplify(x);
+            match x {
+                Expr::Sequence(e) | Expr::Block(_, e) | Expr::Loop(_, e) => {
+                    changed |= simplify_list(e)
+                }
+                Expr::If(_, _, if_true, if_false) => {
+                    changed |= simplify_list(if_true);
+                    changed |= simplify_list(if_false);
+                }
+                _ => {}
+            }
+        });
+    }
+    changed
+}
+
+/// remove redundant instructions from every function, giving back how many
+/// bytes of code were saved
+pub fn optimize(module: &mut Module) -> usize {
+    let mut saved = 0;
+    for f in module.functions.iter_mut() {
+        let before = crate::backend::function_body(f).len();
+        while optimize_list(&mut f.body) {}
+        saved += before.saturating_sub(crate::backend::function_body(f).len());
+    }
+    saved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: i32) -> Expr {
+        Expr::Const(Value::I32(n))
+    }
+
+    fn less_than(a: Expr, b: Expr) -> Expr {
+        Expr::Binary(BinaryOp::Lt, DataType::I32, Box::new(a), Box::new(b))
+    }
+
+    /// optimize a function taking and keeping i32s, giving back its body and
+    /// the bytes saved
+    fn optimized(body: Vec<Expr>) -> (Vec<Expr>, usize) {
+        let mut module = Module {
+            functions: vec![Function {
+                name: "f".to_string(),
+                export: Some("f".to_string()),
+                file: String::new(),
+                inline: false,
+                signature: Signature {
+                    params: vec![DataType::I32],
+                    output: Some(DataType::I32),
+                },
+                locals: vec![DataType::I32],
+                body,
+            }],
+            ..Module::default()
+        };
+        let saved = optimize(&mut module);
+        (module.functions.remove(0).body, saved)
+    }
+
+    #[test]
+    fn setting_a_local_and_reading_it_back_keeps_the_value() {
+        let (body, saved) = optimized(vec![
+            Expr::LocalSet(1, Box::new(Expr::LocalGet(0))),
+            Expr::LocalGet(1),
+        ]);
+        assert_eq!(body, vec![Expr::LocalTee(1, Box::new(Expr::LocalGet(0)))]);
+        assert_eq!(saved, 2);
+        let (body, saved) = optimized(vec![
+            Expr::Drop(Box::new(Expr::LocalTee(1, Box::new(Expr::LocalGet(0))))),
+            Expr::Drop(Box::new(Expr::LocalGet(1))),
+            Expr::LocalGet(1),
+        ]);
+        assert_eq!(body, vec![Expr::LocalTee(1, Box::new(Expr::LocalGet(0)))]);
+        assert_eq!(saved, 6);
+    }
+
+    #[test]
+    fn comparisons_are_not_turned_into_booleans_again() {
+        let compare = || less_than(Expr::LocalGet(0), int(3));
+        let twice = Expr::Eqz(
+            DataType::I32,
+            Box::new(Expr::Eqz(DataType::I32, Box::new(compare()))),
+        );
+        assert_eq!(optimized(vec![twice]), (vec![compare()], 2));
+        let not_zero = Expr::Binary(
+            BinaryOp::Ne,
+            DataType::I32,
+            Box::new(compare()),
+            Box::new(int(0)),
+        );
+        assert_eq!(optimized(vec![not_zero]), (vec![compare()], 3));
+    }
+
+    #[test]
+    fn conversions_there_and_back_are_removed() {
+        let there_and_back = Expr::Convert(
+            DataType::F64,
+            DataType::I32,
+            Box::new(Expr::Convert(
+                DataType::I32,
+                DataType::F64,
+                Box::new(Expr::LocalGet(0)),
+            )),
+        );
+        assert_eq!(
+            optimized(vec![there_and_back]),
+            (vec![Expr::LocalGet(0)], 2)
+        );
+    }
+
+    #[test]
+    fn negated_conditions_swap_the_branches() {
+        let (body, saved) = optimized(vec![Expr::If(
+            Some(DataType::I32),
+            Box::new(Expr::Eqz(DataType::I32, Box::new(Expr::LocalGet(0)))),
+            vec![int(1)],
+            vec![int(2)],
+        )]);
+        assert_eq!(
+            body,
+            vec![Expr::If(
+                Some(DataType::I32),
+                Box::new(Expr::LocalGet(0)),
+                vec![int(2)],
+                vec![int(1)],
+            )]
+        );
+        assert_eq!(saved, 1);
+    }
+}
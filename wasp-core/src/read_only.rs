@@ -0,0 +1,144 @@
+use crate::ast::*;
+use crate::modules::is_builtin_function;
+
+/// builtins that only compute a number from their arguments
+fn is_operator(name: &str) -> bool {
+    is_builtin_function(name)
+        && !matches!(
+            name,
+            "assert" | "call" | "mem" | "mem_byte" | "mem_heap_start" | "mem_heap_end"
+        )
+}
+
+fn is_read(x: &OperationFunctionCall) -> bool {
+    (x.function_name == "mem" || x.function_name == "mem_byte") && x.params.len() == 1
+}
+
+/// does an expression only work out an address from a static, so what is at
+/// it can be read but not written
+fn is_address(e: &Expression, name: &str) -> bool {
+    match e {
+        Expression::Identifier(_) => true,
+        Expression::FunctionCall(x) if is_operator(&x.function_name) => {
+            x.params.iter().all(|p| is_address(p, name))
+        }
+        e => !may_write_through(e, name),
+    }
+}
+
+/// could the address of a static get anywhere it might be written through,
+/// which is anywhere but the address of a read
+fn may_write_through(e: &Expression, name: &str) -> bool {
+    match e {
+        Expression::Identifier(x) => x.name == name,
+        Expression::FunctionCall(x) if is_read(x) => !is_address(&x.params[0], name),
+        Expression::FunctionCall(x) => x.params.iter().any(|p| may_write_through(p, name)),
+        Expression::Assignment(x) => may_write_through(&x.value, name),
+        Expression::IfStatement(x) => {
+            may_write_through(&x.condition, name)
+                || x.if_true
+                    .iter()
+                    .chain(x.if_false.iter().flatten())
+                    .any(|x| may_write_through(x, name))
+        }
+        Expression::Loop(x) => x.expressions.iter().any(|x| may_write_through(x, name)),
+        _ => false,
+    }
+}
+
+/// is a static data without anything else placed in memory within it
+fn is_flat(v: &GlobalValue) -> bool {
+    match v {
+        GlobalValue::Data(values) => values
+            .iter()
+            .all(|v| !matches!(v, GlobalValue::Data(_) | GlobalValue::Struct(_))),
+        _ => true,
+    }
+}
+
+fn stores(v: &GlobalValue, name: &str) -> bool {
+    match v {
+        GlobalValue::Identifier(n) => n == name,
+        GlobalValue::Data(values) => values.iter().any(|v| stores(v, name)),
+        _ => false,
+    }
+}
+
+/// the statics whose data is only ever read, so identical data of others
+/// can be kept in the same place
+pub fn read_only_statics(app: &App) -> Vec<&str> {
+    let globals = app.children.iter().filter_map(|op| match op {
+        TopLevelOperation::DefineGlobal(g) => Some(g),
+        _ => None,
+    });
+    let written = |name: &str| {
+        app.children.iter().any(|op| match op {
+            TopLevelOperation::DefineFunction(f) => {
+                f.children.iter().any(|x| may_write_through(x, name))
+            }
+            // its address is kept in the data of another static
+            TopLevelOperation::DefineGlobal(g) => stores(&g.value, name),
+            _ => false,
+        })
+    };
+    globals
+        .filter(|g| is_flat(&g.value) && !written(&g.name))
+        .map(|g| g.name.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_only(source: &str) -> Vec<String> {
+        let app = crate::parser::parse(source).expect("source parses");
+        read_only_statics(&app)
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn statics_only_read_from_are_read_only() {
+        let source = "static a = (1, 2)
+static b = (1, 2)
+static c = (1, 2)
+static d = (1, 2)
+static e = (1, 2)
+static f = (c, 2)
+static g = ((1), 2)
+fn read(i) { (mem((a + (i * size_num))) + mem_byte(mem(b))) }
+fn write() { mem(c, 5) }
+fn pass() { read(d) }
+fn keep() { x = e x }";
+        // f only holds the address of c, it is c that may be written
+        assert_eq!(read_only(source), vec!["a", "b", "f"]);
+    }
+
+    #[test]
+    fn statics_written_through_keep_their_own_data() {
+        let source = "static a = (0)
+static b = (0)
+static c = (0)
+static d = (0)
+pub fn f() { mem(a, 100) mem(b) }
+pub fn g() { (mem(c) + mem(d)) }";
+        for level in crate::testing::OPT_LEVELS {
+            let wasm = crate::testing::compile_at(source, *level);
+            assert_eq!(crate::testing::run(&wasm, "f", &[]).0, 0.0, "{:?}", level);
+        }
+        let app = crate::parser::parse(source).expect("source parses");
+        let output = crate::compiler::compile_with_options(app, &Default::default())
+            .expect("source compiles");
+        let offsets = output
+            .module
+            .placements
+            .iter()
+            .map(|p| (p.label.clone(), p.offset))
+            .collect::<Vec<(String, u32)>>();
+        assert_eq!(offsets.len(), 4);
+        assert_ne!(offsets[0].1, offsets[1].1);
+        assert_eq!(offsets[2].1, offsets[3].1);
+    }
+}
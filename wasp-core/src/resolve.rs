@@ -0,0 +1,224 @@
+use crate::ast::*;
+use crate::modules::{is_builtin_function, is_builtin_identifier, unqualified_name};
+use failure::Error;
+
+/// the most suggestions given for one unknown name
+const MAX_SUGGESTIONS: usize = 3;
+
+/// number of single character edits needed to turn one name into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+struct Resolver<'a> {
+    functions: Vec<&'a str>,
+    globals: Vec<&'a str>,
+    /// the function being checked
+    function: &'a str,
+    locals: Vec<&'a str>,
+    /// every name assigned anywhere in the function being checked
+    assigned: Vec<&'a str>,
+    errors: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    /// how a definition is best written, unqualified unless that would be ambiguous
+    fn display_name(&self, name: &'a str) -> &'a str {
+        let short = unqualified_name(name);
+        let same_short = self
+            .functions
+            .iter()
+            .chain(self.globals.iter())
+            .filter(|n| unqualified_name(n) == short)
+            .count();
+        if same_short > 1 {
+            name
+        } else {
+            short
+        }
+    }
+
+    /// close matches to an unknown name, closest first
+    fn suggestions(&self, name: &str, candidates: Vec<&'a str>) -> String {
+        let name = unqualified_name(name);
+        let limit = std::cmp::max(1, name.chars().count() / 3);
+        let mut close = candidates
+            .into_iter()
+            .map(|c| (edit_distance(name, unqualified_name(c)), c))
+            .filter(|(d, _)| *d <= limit)
+            .collect::<Vec<(usize, &str)>>();
+        close.sort();
+        let mut names: Vec<&str> = vec![];
+        for (_, c) in close {
+            if !names.contains(&c) {
+                names.push(c);
+            }
+        }
+        names.truncate(MAX_SUGGESTIONS);
+        match names.len() {
+            0 => String::new(),
+            1 => format!(", did you mean {}?", names[0]),
+            _ => format!(", did you mean one of {}?", names.join(", ")),
+        }
+    }
+
+    fn check_identifier(&mut self, x: &'a OperationIdentifier) {
+        let name = x.name.as_str();
+        if is_builtin_identifier(name)
+            || self.locals.contains(&name)
+            || self.functions.contains(&name)
+            || self.globals.contains(&name)
+        {
+            return;
+        }
+        if self.assigned.contains(&name) {
+            self.errors.push(format!(
+                "{}: {} is used before it is assigned in function {}",
+                x.location, name, self.function
+            ));
+            return;
+        }
+        let mut candidates = self.locals.clone();
+        for n in self.functions.iter().chain(self.globals.iter()) {
+            candidates.push(self.display_name(n));
+        }
+        let suggestion = self.suggestions(name, candidates);
+        self.errors.push(format!(
+            "{}: {} is never defined{}",
+            x.location, name, suggestion
+        ));
+    }
+
+    fn check_call(&mut self, x: &'a OperationFunctionCall) {
+        let name = x.function_name.as_str();
+        if is_builtin_function(name) {
+            return;
+        }
+        // locals shadow functions of the same name
+        if self.locals.contains(&name)
+            || (!self.functions.contains(&name) && self.globals.contains(&name))
+        {
+            self.errors.push(format!(
+                "{}: {} is not a function, use call to call a function by its handle",
+                x.location, name
+            ));
+            return;
+        }
+        if self.functions.contains(&name) {
+            return;
+        }
+        let mut candidates = vec![];
+        for n in self.functions.iter() {
+            candidates.push(self.display_name(n));
+        }
+        let suggestion = self.suggestions(name, candidates);
+        self.errors.push(format!(
+            "{}: function {} is never defined{}",
+            x.location, name, suggestion
+        ));
+    }
+
+    fn check_expression(&mut self, e: &'a Expression) {
+        match e {
+            Expression::Identifier(x) => self.check_identifier(x),
+            Expression::FunctionCall(x) => self.check_call(x),
+            Expression::Assignment(x) => self.locals.push(&x.id),
+            _ => {}
+        }
+    }
+
+    /// statics can use functions and the statics defined before them
+    fn check_global_value(&mut self, global: &'a Global, v: &'a GlobalValue, defined: &[&str]) {
+        match v {
+            GlobalValue::Identifier(name) => {
+                let name = name.as_str();
+                if is_builtin_identifier(name)
+                    || self.functions.contains(&name)
+                    || defined.contains(&name)
+                {
+                    return;
+                }
+                if self.globals.contains(&name) {
+                    self.errors.push(format!(
+                        "{}: {} is used by static {} before it is defined",
+                        global.location, name, global.name
+                    ));
+                    return;
+                }
+                let mut candidates = vec![];
+                for n in self.functions.iter().chain(defined.iter()) {
+                    candidates.push(self.display_name(n));
+                }
+                let suggestion = self.suggestions(name, candidates);
+                self.errors.push(format!(
+                    "{}: {} used by static {} is never defined{}",
+                    global.location, name, global.name, suggestion
+                ));
+            }
+            GlobalValue::Data(values) => {
+                for v in values.iter() {
+                    self.check_global_value(global, v, defined);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// make sure every identifier and function name refers to something, reporting
+/// all that don't at once with suggestions of what might have been meant
+pub fn check_names(app: &App) -> Result<(), Error> {
+    let mut resolver = Resolver {
+        functions: vec![],
+        globals: vec![],
+        function: "",
+        locals: vec![],
+        assigned: vec![],
+        errors: vec![],
+    };
+    for op in app.children.iter() {
+        match op {
+            TopLevelOperation::DefineFunction(f) => resolver.functions.push(&f.name),
+            TopLevelOperation::ExternalFunction(f) => resolver.functions.push(&f.name),
+            TopLevelOperation::DefineGlobal(g) => resolver.globals.push(&g.name),
+            _ => {}
+        }
+    }
+
+    let mut defined = vec![];
+    for op in app.children.iter() {
+        if let TopLevelOperation::DefineGlobal(g) = op {
+            resolver.check_global_value(g, &g.value, &defined);
+            defined.push(g.name.as_str());
+        }
+    }
+
+    for op in app.children.iter() {
+        if let TopLevelOperation::DefineFunction(f) = op {
+            resolver.function = &f.name;
+            resolver.locals = f.params.iter().map(|p| p.as_str()).collect();
+            resolver.assigned = assignments(&f.children)
+                .iter()
+                .map(|a| a.id.as_str())
+                .collect();
+            for x in f.children.iter() {
+                walk(x, 0, &mut |x, _| resolver.check_expression(x));
+            }
+        }
+    }
+
+    if !resolver.errors.is_empty() {
+        return Err(format_err!("{}", resolver.errors.join("\n")));
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use crate::ast::*;
+use std::collections::{HashMap, HashSet};
+
+/// a top level definition something can refer to by name
+enum Definition<'a> {
+    Function(&'a FunctionDefinition),
+    Extern,
+    Static(&'a GlobalValue),
+}
+
+/// finds every definition that can be reached from the exported functions
+struct Reachability<'a> {
+    definitions: HashMap<&'a str, Definition<'a>>,
+    reached: HashSet<&'a str>,
+    pending: Vec<&'a str>,
+}
+
+impl<'a> Reachability<'a> {
+    fn reach(&mut self, name: &'a str) {
+        if self.definitions.contains_key(name) && self.reached.insert(name) {
+            self.pending.push(name);
+        }
+    }
+
+    fn visit_block(&mut self, e: &'a [Expression]) {
+        for x in e.iter() {
+            walk(x, 0, &mut |x, _| match x {
+                // a function named as a value has its handle taken
+                Expression::Identifier(x) => self.reach(&x.name),
+                Expression::FunctionCall(x) => self.reach(&x.function_name),
+                _ => {}
+            });
+        }
+    }
+
+    fn visit_static(&mut self, v: &'a GlobalValue) {
+        match v {
+            GlobalValue::Identifier(name) => self.reach(name),
+            GlobalValue::Data(values) => {
+                for v in values.iter() {
+                    self.visit_static(v);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// names of the functions, externs and statics the exported functions can
+/// reach by calling them, taking their handles or reading them
+fn reachable(app: &App) -> HashSet<String> {
+    let mut r = Reachability {
+        definitions: HashMap::new(),
+        reached: HashSet::new(),
+        pending: vec![],
+    };
+    for op in app.children.iter() {
+        match op {
+            TopLevelOperation::DefineFunction(f) => {
+                r.definitions.insert(&f.name, Definition::Function(f));
+            }
+            TopLevelOperation::ExternalFunction(f) => {
+                r.definitions.insert(&f.name, Definition::Extern);
+            }
+            TopLevelOperation::DefineGlobal(g) => {
+                r.definitions.insert(&g.name, Definition::Static(&g.value));
+            }
+            _ => {}
+        }
+    }
+    for op in app.children.iter() {
+        if let TopLevelOperation::DefineFunction(f) = op {
+            if f.exported {
+                r.reach(&f.name);
+            }
+        }
+    }
+    while let Some(name) = r.pending.pop() {
+        match r.definitions[name] {
+            Definition::Function(f) => r.visit_block(&f.children),
+            Definition::Static(v) => r.visit_static(v),
+            Definition::Extern => {}
+        }
+    }
+    r.reached.into_iter().map(|name| name.to_string()).collect()
+}
+
+/// remove the functions, externs and statics exported functions can never
+/// reach, so nothing is emitted for them
+pub fn remove_unreachable(app: App) -> App {
+    let reached = reachable(&app);
+    App {
+        children: app
+            .children
+            .into_iter()
+            .filter(|op| match op {
+                TopLevelOperation::DefineFunction(f) => reached.contains(&f.name),
+                TopLevelOperation::ExternalFunction(f) => reached.contains(&f.name),
+                TopLevelOperation::DefineGlobal(g) => reached.contains(&g.name),
+                _ => true,
+            })
+            .collect(),
+    }
+}
+
+/// remove the functions and externs of a module that are no longer called
+/// or have their handle taken, such as ones inlined everywhere they were used
+pub fn remove_uncalled(module: &mut crate::ir::Module) {
+    use crate::ir::{walk, walk_mut, Expr};
+    let imports = module.imports.len();
+    let count = imports + module.functions.len();
+    let mut reached = vec![false; count];
+    let mut pending = module.table.clone();
+    for (i, f) in module.functions.iter().enumerate() {
+        if f.export.is_some() {
+            pending.push((imports + i) as u32);
+        }
+    }
+    while let Some(idx) = pending.pop() {
+        let idx = idx as usize;
+        if reached[idx] {
+            continue;
+        }
+        reached[idx] = true;
+        if idx >= imports {
+            for x in module.functions[idx - imports].body.iter() {
+                walk(x, 0, &mut |x, _| {
+                    if let Expr::Call(g, _) = x {
+                        pending.push(*g);
+                    }
+                });
+            }
+        }
+    }
+    if reached.iter().all(|r| *r) {
+        return;
+    }
+
+    let mut positions = vec![0; count];
+    let mut position = 0;
+    for (idx, r) in reached.iter().enumerate() {
+        positions[idx] = position;
+        if *r {
+            position += 1;
+        }
+    }
+    let mut idx = 0;
+    module.imports.retain(|_| {
+        idx += 1;
+        reached[idx - 1]
+    });
+    module.functions.retain(|_| {
+        idx += 1;
+        reached[idx - 1]
+    });
+    for f in module.functions.iter_mut() {
+        for x in f.body.iter_mut() {
+            walk_mut(x, 0, &mut |x, _| {
+                if let Expr::Call(g, _) = x {
+                    *g = positions[*g as usize];
+                }
+            });
+        }
+    }
+    for f in module.table.iter_mut() {
+        *f = positions[*f as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_reached_from_exported_functions_are_kept() {
+        let source = "extern log(x)
+extern unused(x)
+static table = (g, 1)
+static other = (2)
+pub fn main() { loop { if (1 == 2) { x = call(mem(table), 1) } else { recur } } }
+fn g(x) { log(x) }
+fn h() { mem(other) }";
+        let app = crate::parser::parse(source).expect("source parses");
+        let mut reached = reachable(&app).into_iter().collect::<Vec<String>>();
+        reached.sort();
+        assert_eq!(reached, vec!["g", "log", "main", "table"]);
+    }
+}
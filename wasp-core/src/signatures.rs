@@ -0,0 +1,386 @@
+use crate::ast::*;
+use failure::Error;
+use std::collections::{BTreeSet, HashMap};
+use wasmly::DataType;
+
+/// something a value may hold that matters for indirect calls
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Fact<'a> {
+    /// the handle of a function
+    Function(&'a str),
+    /// a pointer into the data of a static
+    Data(&'a str),
+}
+
+type Facts<'a> = BTreeSet<Fact<'a>>;
+
+struct Signature<'a> {
+    params: &'a [DataType],
+    output: &'a Option<DataType>,
+    location: &'a Location,
+}
+
+fn describe_type(t: &DataType) -> &'static str {
+    match t {
+        DataType::I32 => "i32",
+        DataType::I64 => "i64",
+        DataType::F32 => "f32",
+        DataType::F64 => "f64",
+    }
+}
+
+fn describe_signature(params: &[DataType], output: &Option<DataType>) -> String {
+    format!(
+        "fn({})->{}",
+        params
+            .iter()
+            .map(describe_type)
+            .collect::<Vec<&str>>()
+            .join(","),
+        output.as_ref().map(describe_type).unwrap_or("()")
+    )
+}
+
+/// follows which function handles flow where, through locals, params,
+/// returns, statics and the memory of statics
+struct Analysis<'a> {
+    signatures: HashMap<&'a str, Signature<'a>>,
+    functions: HashMap<&'a str, &'a FunctionDefinition>,
+    statics: HashMap<&'a str, &'a GlobalValue>,
+    /// what the locals and params of each function may hold
+    locals: HashMap<(&'a str, &'a str), Facts<'a>>,
+    /// what each function may return
+    returns: HashMap<&'a str, Facts<'a>>,
+    /// what the data of each static may hold
+    contents: HashMap<&'a str, Facts<'a>>,
+    changed: bool,
+    errors: Vec<String>,
+}
+
+impl<'a> Analysis<'a> {
+    fn add(&mut self, to: Facts<'a>, key: Key<'a>) {
+        let facts = match key {
+            Key::Local(f, name) => self.locals.entry((f, name)).or_default(),
+            Key::Return(f) => self.returns.entry(f).or_default(),
+            Key::Contents(s) => self.contents.entry(s).or_default(),
+        };
+        for fact in to {
+            if facts.insert(fact) {
+                self.changed = true;
+            }
+        }
+    }
+
+    fn is_local(&self, function: &'a FunctionDefinition, name: &str) -> bool {
+        function.params.iter().any(|p| p == name)
+            || self.locals.contains_key(&(&function.name, name))
+    }
+
+    /// what a function or static named outside of a function holds
+    fn global_facts(&self, name: &'a str) -> Facts<'a> {
+        let mut facts = Facts::new();
+        if self.signatures.contains_key(name) {
+            facts.insert(Fact::Function(name));
+            return facts;
+        }
+        match self.statics.get(name) {
+            // statics can only use the statics defined before them
+            Some(GlobalValue::Identifier(target)) if target != name => {
+                facts = self.global_facts(target);
+            }
+            Some(GlobalValue::Data(_)) => {
+                facts.insert(Fact::Data(name));
+            }
+            _ => {}
+        }
+        facts
+    }
+
+    fn eval_block(&mut self, f: &'a FunctionDefinition, e: &'a [Expression]) -> Facts<'a> {
+        let mut last = Facts::new();
+        for x in e.iter() {
+            last = self.eval(f, x);
+        }
+        last
+    }
+
+    fn eval(&mut self, f: &'a FunctionDefinition, e: &'a Expression) -> Facts<'a> {
+        match e {
+            Expression::Identifier(x) => {
+                let name = x.name.as_str();
+                if self.is_local(f, name) {
+                    self.locals
+                        .get(&(&f.name, name))
+                        .cloned()
+                        .unwrap_or_default()
+                } else {
+                    self.global_facts(name)
+                }
+            }
+            Expression::Assignment(x) => {
+                let value = self.eval(f, &x.value);
+                self.add(value.clone(), Key::Local(&f.name, &x.id));
+                value
+            }
+            Expression::IfStatement(x) => {
+                self.eval(f, &x.condition);
+                let mut facts = self.eval_block(f, &x.if_true);
+                if let Some(if_false) = &x.if_false {
+                    facts.extend(self.eval_block(f, if_false));
+                }
+                facts
+            }
+            Expression::Loop(x) => self.eval_block(f, &x.expressions),
+            Expression::FunctionCall(x) => self.eval_call(f, x),
+            _ => Facts::new(),
+        }
+    }
+
+    fn eval_call(&mut self, f: &'a FunctionDefinition, x: &'a OperationFunctionCall) -> Facts<'a> {
+        let params = x
+            .params
+            .iter()
+            .map(|p| self.eval(f, p))
+            .collect::<Vec<Facts>>();
+        match x.function_name.as_str() {
+            // pointer arithmetic keeps pointing into the same static
+            "+" | "-" => params
+                .into_iter()
+                .flatten()
+                .filter(|fact| matches!(fact, Fact::Data(_)))
+                .collect(),
+            "mem" => {
+                let statics = params[0]
+                    .iter()
+                    .filter_map(|fact| match fact {
+                        Fact::Data(s) => Some(*s),
+                        _ => None,
+                    })
+                    .collect::<Vec<&str>>();
+                if params.len() == 2 {
+                    for s in statics {
+                        self.add(params[1].clone(), Key::Contents(s));
+                    }
+                    Facts::new()
+                } else {
+                    statics
+                        .iter()
+                        .flat_map(|s| self.contents.get(s).cloned().unwrap_or_default())
+                        .collect()
+                }
+            }
+            "call" => {
+                let mut facts = Facts::new();
+                if params.len() < 2 {
+                    return facts;
+                }
+                let targets = params[1].clone();
+                for target in targets.iter() {
+                    if let Fact::Function(g) = target {
+                        facts.extend(self.returns.get(g).cloned().unwrap_or_default());
+                        self.pass_arguments(g, &params[2..]);
+                    }
+                }
+                facts
+            }
+            name => {
+                if let Some(g) = self.functions.get(name).map(|g| g.name.as_str()) {
+                    self.pass_arguments(g, &params);
+                    self.returns.get(g).cloned().unwrap_or_default()
+                } else {
+                    Facts::new()
+                }
+            }
+        }
+    }
+
+    fn pass_arguments(&mut self, function: &'a str, arguments: &[Facts<'a>]) {
+        let params = match self.functions.get(function) {
+            Some(g) => &g.params,
+            None => return,
+        };
+        for (p, a) in params.iter().zip(arguments.iter()) {
+            self.add(a.clone(), Key::Local(function, p));
+        }
+    }
+
+    fn check_block(&mut self, f: &'a FunctionDefinition, e: &'a [Expression]) {
+        for x in e.iter() {
+            walk(x, 0, &mut |x, _| {
+                if let Expression::FunctionCall(x) = x {
+                    if x.function_name == "call" {
+                        self.check_call(f, x);
+                    }
+                }
+            });
+        }
+    }
+
+    fn check_call(&mut self, f: &'a FunctionDefinition, x: &'a OperationFunctionCall) {
+        let sig = match x.params.first() {
+            Some(Expression::FnSig(sig)) => sig,
+            _ => return,
+        };
+        let expected = describe_signature(&sig.inputs, &sig.output);
+        let arguments = x.params.len().saturating_sub(2);
+        if arguments != sig.inputs.len() {
+            self.errors.push(format!(
+                "{}: call with signature {} is given {} arguments",
+                x.location, expected, arguments
+            ));
+        }
+        let targets = match x.params.get(1) {
+            Some(handle) => self.eval(f, handle),
+            None => return,
+        };
+        for target in targets {
+            if let Fact::Function(name) = target {
+                let s = &self.signatures[name];
+                if s.params != sig.inputs.as_slice() || *s.output != sig.output {
+                    self.errors.push(format!(
+                        "{}: call with signature {} may call {} which is {}\n  {}: {} defined here",
+                        x.location,
+                        expected,
+                        name,
+                        describe_signature(s.params, s.output),
+                        s.location,
+                        name
+                    ));
+                }
+            }
+        }
+    }
+}
+
+enum Key<'a> {
+    Local(&'a str, &'a str),
+    Return(&'a str),
+    Contents(&'a str),
+}
+
+/// make sure the signature of every indirect `call` matches the functions
+/// whose handles can reach it
+pub fn check_signatures(app: &App) -> Result<(), Error> {
+    let mut analysis = Analysis {
+        signatures: HashMap::new(),
+        functions: HashMap::new(),
+        statics: HashMap::new(),
+        locals: HashMap::new(),
+        returns: HashMap::new(),
+        contents: HashMap::new(),
+        changed: false,
+        errors: vec![],
+    };
+    for op in app.children.iter() {
+        match op {
+            TopLevelOperation::DefineFunction(f) => {
+                analysis.functions.insert(&f.name, f);
+                analysis.signatures.insert(
+                    &f.name,
+                    Signature {
+                        params: &f.param_types,
+                        output: &f.output,
+                        location: &f.location,
+                    },
+                );
+            }
+            TopLevelOperation::ExternalFunction(f) => {
+                analysis.signatures.insert(
+                    &f.name,
+                    Signature {
+                        params: &f.param_types,
+                        output: &f.output,
+                        location: &f.location,
+                    },
+                );
+            }
+            TopLevelOperation::DefineGlobal(g) => {
+                analysis.statics.insert(&g.name, &g.value);
+            }
+            _ => {}
+        }
+    }
+
+    // handles the data of statics starts out with
+    let mut contents = vec![];
+    for (name, value) in analysis.statics.iter() {
+        if let GlobalValue::Data(values) = value {
+            let mut facts = Facts::new();
+            for v in values.iter() {
+                if let GlobalValue::Identifier(target) = v {
+                    facts.extend(analysis.global_facts(target));
+                }
+            }
+            contents.push((*name, facts));
+        }
+    }
+    for (name, facts) in contents {
+        analysis.add(facts, Key::Contents(name));
+    }
+
+    let functions = app
+        .children
+        .iter()
+        .filter_map(|op| match op {
+            TopLevelOperation::DefineFunction(f) => Some(f),
+            _ => None,
+        })
+        .collect::<Vec<&FunctionDefinition>>();
+    loop {
+        analysis.changed = false;
+        for f in functions.iter() {
+            let returned = analysis.eval_block(f, &f.children);
+            analysis.add(returned, Key::Return(&f.name));
+        }
+        if !analysis.changed {
+            break;
+        }
+    }
+    for f in functions.iter() {
+        analysis.check_block(f, &f.children);
+    }
+
+    if !analysis.errors.is_empty() {
+        return Err(format_err!("{}", analysis.errors.join("\n")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: &str) -> Result<(), String> {
+        let app = crate::parser::parse(source).expect("source parses");
+        check_signatures(&app).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn calls_match_the_functions_their_handles_come_from() {
+        assert!(check("fn g(x) { x }\nfn f() { call(fn(f64)->f64, g, 1) }").is_ok());
+        assert_eq!(
+            check("fn g(x) { x }\nfn f() { call(fn()->f64, g) }").unwrap_err(),
+            "2:10: call with signature fn()->f64 may call g which is fn(f64)->f64\n  1:1: g defined here"
+        );
+        assert_eq!(
+            check("fn g() { 1 }\nfn f() { call(fn()->f64, g, 1) }").unwrap_err(),
+            "2:10: call with signature fn()->f64 is given 1 arguments"
+        );
+    }
+
+    #[test]
+    fn handles_are_followed_through_locals_returns_and_statics() {
+        let source = "extern log(x: i32) -> ()
+static handlers = (log)
+fn pick() { mem(handlers) }
+fn f(a) { if a { h = pick() } else { h = 0 } loop { call(fn(f64)->f64, h, 1) } }";
+        assert_eq!(
+            check(source).unwrap_err(),
+            "4:53: call with signature fn(f64)->f64 may call log which is fn(i32)->()\n  1:1: log defined here"
+        );
+        let source = "fn g(x) { x }
+fn apply(h, x) { call(fn(f64)->f64, h, x) }
+fn f() { apply(g, 1) }";
+        assert!(check(source).is_ok());
+    }
+}
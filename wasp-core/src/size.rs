@@ -0,0 +1,211 @@
+use crate::ir::*;
+use std::fmt;
+
+pub struct FunctionSize {
+    pub name: String,
+    pub file: String,
+    /// bytes of the function's entry in the code section
+    pub size: usize,
+}
+
+pub struct DataSize {
+    pub origin: DataOrigin,
+    /// how many literals or statics
+    pub count: usize,
+    /// bytes of the data placed in memory
+    pub size: usize,
+}
+
+/// what the bytes of a module are spent on
+pub struct SizeReport {
+    /// largest first
+    pub functions: Vec<FunctionSize>,
+    pub data: Vec<DataSize>,
+    pub sections: Vec<(String, usize)>,
+    pub total: usize,
+}
+
+fn describe_origin(origin: DataOrigin) -> &'static str {
+    match origin {
+        DataOrigin::Text => "string literals",
+        DataOrigin::Static => "static data",
+        DataOrigin::Struct => "struct definitions",
+    }
+}
+
+/// how much of a module comes from each function, data and section
+pub fn size_report(module: &Module, wasm: &[u8]) -> SizeReport {
+    let mut functions = module
+        .functions
+        .iter()
+        .map(|f| {
+            let body = crate::backend::function_body(f).len();
+            let mut length = vec![];
+            crate::backend::uleb(&mut length, body as u64);
+            FunctionSize {
+                name: f.name.clone(),
+                file: f.file.clone(),
+                size: length.len() + body,
+            }
+        })
+        .collect::<Vec<FunctionSize>>();
+    functions.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+    let mut data: Vec<DataSize> = vec![];
+    for p in module.placements.iter().filter(|p| !p.shared) {
+        match data.iter_mut().find(|s| s.origin == p.origin) {
+            Some(s) => {
+                s.count += 1;
+                s.size += p.size as usize;
+            }
+            None => data.push(DataSize {
+                origin: p.origin,
+                count: 1,
+                size: p.size as usize,
+            }),
+        }
+    }
+
+    SizeReport {
+        functions,
+        data,
+        sections: crate::backend::section_sizes(wasm),
+        total: wasm.len(),
+    }
+}
+
+impl SizeReport {
+    fn share(&self, size: usize) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            size as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .functions
+            .iter()
+            .map(|x| x.name.len())
+            .chain(std::iter::once("function".len()))
+            .max()
+            .unwrap_or(0);
+        let file_width = self
+            .functions
+            .iter()
+            .map(|x| x.file.len())
+            .chain(std::iter::once("file".len()))
+            .max()
+            .unwrap_or(0);
+        writeln!(
+            f,
+            "{:<width$}  {:<file_width$}  {:>8}  {:>6}",
+            "function",
+            "file",
+            "bytes",
+            "share",
+            width = width,
+            file_width = file_width
+        )?;
+        for x in self.functions.iter() {
+            writeln!(
+                f,
+                "{:<width$}  {:<file_width$}  {:>8}  {:>5.1}%",
+                x.name,
+                x.file,
+                x.size,
+                self.share(x.size),
+                width = width,
+                file_width = file_width
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<18}  {:>8}  {:>8}  {:>6}",
+            "data", "count", "bytes", "share"
+        )?;
+        for x in self.data.iter() {
+            writeln!(
+                f,
+                "{:<18}  {:>8}  {:>8}  {:>5.1}%",
+                describe_origin(x.origin),
+                x.count,
+                x.size,
+                self.share(x.size)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<18}  {:>8}  {:>6}", "section", "bytes", "share")?;
+        for (name, size) in self.sections.iter() {
+            writeln!(f, "{:<18}  {:>8}  {:>5.1}%", name, size, self.share(*size))?;
+        }
+        write!(f, "{:<18}  {:>8}", "total", self.total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{OptLevel, Options};
+
+    fn report(source: &str, opt_level: OptLevel) -> SizeReport {
+        let app = crate::parser::parse(source).expect("source parses");
+        let options = Options {
+            opt_level,
+            ..Default::default()
+        };
+        let output = crate::compiler::compile_with_options(app, &options).expect("source compiles");
+        size_report(&output.module, &output.wasm)
+    }
+
+    #[test]
+    fn every_byte_is_accounted_for() {
+        let report = report(
+            "static numbers = (1, 2, 3)
+pub fn small() { 1 }
+pub fn large(x) { if (x > 1) { (mem(numbers) + \"hello\") } else { \"hello\" } }",
+            OptLevel::MinSize,
+        );
+        let names = report
+            .functions
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["large", "small"]);
+        // its length, no locals, an f64.const and the end
+        assert_eq!(report.functions[1].size, 12);
+        // the functions and how many there are make up the code section
+        let code = report
+            .sections
+            .iter()
+            .find(|(name, _)| name == "code")
+            .expect("a code section")
+            .1;
+        let functions = report.functions.iter().map(|f| f.size).sum::<usize>();
+        assert_eq!(code, 2 + 1 + functions);
+        // the magic number and version come before the sections
+        let sections = report.sections.iter().map(|(_, s)| s).sum::<usize>();
+        assert_eq!(8 + sections, report.total);
+        // the same literal twice is only placed once when sharing data
+        let data = report
+            .data
+            .iter()
+            .map(|d| (describe_origin(d.origin), d.count))
+            .collect::<Vec<(&str, usize)>>();
+        assert_eq!(data, vec![("static data", 1), ("string literals", 1)]);
+    }
+
+    #[test]
+    fn reports_are_shown_as_tables() {
+        let text = report("pub fn main() { 1 }", OptLevel::None).to_string();
+        let lines = text.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "function  file     bytes   share");
+        assert!(lines[1].starts_with("main      "));
+        assert!(lines.contains(&"data                   count     bytes   share"));
+        assert!(lines.last().unwrap().starts_with("total"));
+    }
+}
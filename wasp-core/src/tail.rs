@@ -0,0 +1,286 @@
+use crate::ir::*;
+use wasmly::DataType;
+
+/// visit every call in tail position of an expression, whose value (if any)
+/// is what the function returns, with how many labels deep it is. When
+/// `discarded` the value of the expression is thrown away, as it is at the
+/// end of a function returning nothing.
+pub fn tail_calls(e: &mut Expr, depth: u32, discarded: bool, f: &mut impl FnMut(&mut Expr, u32)) {
+    match e {
+        Expr::Call(_, _) | Expr::CallIndirect(_, _, _) => f(e, depth),
+        Expr::Return(Some(v)) => tail_calls(v, depth, false, f),
+        Expr::Drop(v) if discarded => tail_calls(v, depth, true, f),
+        Expr::Sequence(e) => {
+            let len = e.len();
+            match e.last() {
+                // the placeholder value of a call returning nothing
+                Some(Expr::Const(_)) if discarded && len > 1 => {
+                    tail_calls(&mut e[len - 2], depth, true, f)
+                }
+                Some(_) => tail_calls(&mut e[len - 1], depth, discarded, f),
+                None => {}
+            }
+        }
+        Expr::Block(_, e) | Expr::Loop(_, e) => {
+            if let Some(x) = e.last_mut() {
+                tail_calls(x, depth + 1, discarded, f);
+            }
+        }
+        Expr::If(_, _, if_true, if_false) => {
+            for x in if_true.last_mut().into_iter().chain(if_false.last_mut()) {
+                tail_calls(x, depth + 1, discarded, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// does an expression read a local
+fn reads(e: &Expr, local: u32) -> bool {
+    let mut found = false;
+    walk(e, 0, &mut |x, _| {
+        if let Expr::LocalGet(i) = x {
+            found |= *i == local;
+        }
+    });
+    found
+}
+
+/// turn calls a function makes to itself in tail position into rebinding its
+/// params and branching back to a loop around its body, so recursing doesn't
+/// grow the stack. Locals read before they are set are zeroed again too, as
+/// they would be for a call.
+fn eliminate(f: &mut Function, idx: u32) {
+    let discarded = f.signature.output.is_none();
+    let params = f.signature.params.clone();
+    let zeroed = crate::locals::read_before_set(f)
+        .into_iter()
+        .map(|i| (i, f.locals[i as usize - params.len()].clone()))
+        .collect::<Vec<(u32, DataType)>>();
+    let mut locals = (params.len() + f.locals.len()) as u32;
+    let mut temporaries = vec![];
+    let mut found = false;
+    if let Some(x) = f.body.last_mut() {
+        tail_calls(x, 0, discarded, &mut |x, _| {
+            found |= matches!(x, Expr::Call(i, _) if *i == idx);
+        });
+    }
+    if !found {
+        return;
+    }
+    // branches out of the function now have to get past the loop too
+    for x in f.body.iter_mut() {
+        walk_mut(x, 0, &mut |x, depth| match x {
+            Expr::Br(n) if *n >= depth => *n += 1,
+            _ => {}
+        });
+    }
+    if let Some(x) = f.body.last_mut() {
+        tail_calls(x, 0, discarded, &mut |x, depth| {
+            let args = match x {
+                Expr::Call(i, args) if *i == idx => std::mem::take(args),
+                _ => return,
+            };
+            let mut jump = vec![];
+            let mut rebinds = vec![];
+            for (p, arg) in args.iter().enumerate() {
+                let p = p as u32;
+                if *arg == Expr::LocalGet(p) {
+                    continue;
+                }
+                // a param read by a later argument can only change after it
+                if args[p as usize + 1..].iter().any(|a| reads(a, p)) {
+                    temporaries.push(params[p as usize].clone());
+                    jump.push(Expr::LocalSet(locals, Box::new(arg.clone())));
+                    rebinds.push(Expr::LocalSet(p, Box::new(Expr::LocalGet(locals))));
+                    locals += 1;
+                } else {
+                    jump.push(Expr::LocalSet(p, Box::new(arg.clone())));
+                }
+            }
+            jump.extend(rebinds);
+            for (i, t) in zeroed.iter() {
+                jump.push(Expr::LocalSet(*i, Box::new(Expr::Const(Value::zero(t)))));
+            }
+            jump.push(Expr::Br(depth));
+            *x = Expr::Sequence(jump);
+        });
+    }
+    f.locals.extend(temporaries);
+    let body = std::mem::take(&mut f.body);
+    f.body = vec![Expr::Loop(f.signature.output.clone(), body)];
+}
+
+/// eliminate the self recursive tail calls of every function
+pub fn eliminate_tail_calls(module: &mut Module) {
+    let imports = module.imports.len();
+    for (i, f) in module.functions.iter_mut().enumerate() {
+        eliminate(f, (imports + i) as u32);
+    }
+}
+
+/// make every call in tail position to a function returning the same as the
+/// caller a tail call, so functions calling each other don't grow the stack
+pub fn emit_tail_calls(module: &mut Module) {
+    let outputs = module
+        .imports
+        .iter()
+        .map(|f| &f.signature)
+        .chain(module.functions.iter().map(|f| &f.signature))
+        .map(|s| s.output.clone())
+        .collect::<Vec<Option<DataType>>>();
+    let types = &module.types;
+    for f in module.functions.iter_mut() {
+        let output = f.signature.output.clone();
+        if let Some(x) = f.body.last_mut() {
+            tail_calls(x, 0, output.is_none(), &mut |x, _| {
+                let callee_output = match x {
+                    Expr::Call(g, _) => &outputs[*g as usize],
+                    Expr::CallIndirect(t, _, _) => &types[*t as usize].output,
+                    _ => return,
+                };
+                if *callee_output == output {
+                    let call = std::mem::replace(x, Expr::Sequence(vec![]));
+                    *x = Expr::TailCall(Box::new(call));
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::OptLevel;
+    use crate::testing::{compile_at, run};
+
+    fn int(n: i32) -> Expr {
+        Expr::Const(Value::I32(n))
+    }
+
+    fn countdown(body: Vec<Expr>) -> Module {
+        Module {
+            functions: vec![Function {
+                name: "f".to_string(),
+                export: Some("f".to_string()),
+                file: String::new(),
+                inline: false,
+                signature: Signature {
+                    params: vec![DataType::I32],
+                    output: Some(DataType::I32),
+                },
+                locals: vec![],
+                body,
+            }],
+            ..Module::default()
+        }
+    }
+
+    fn minus_one() -> Expr {
+        Expr::Binary(
+            BinaryOp::Sub,
+            DataType::I32,
+            Box::new(Expr::LocalGet(0)),
+            Box::new(int(1)),
+        )
+    }
+
+    #[test]
+    fn self_tail_calls_become_a_loop() {
+        let mut module = countdown(vec![Expr::If(
+            Some(DataType::I32),
+            Box::new(Expr::Eqz(DataType::I32, Box::new(Expr::LocalGet(0)))),
+            vec![int(7)],
+            vec![Expr::Call(0, vec![minus_one()])],
+        )]);
+        eliminate_tail_calls(&mut module);
+        let body = &module.functions[0].body;
+        assert!(matches!(body[..], [Expr::Loop(Some(DataType::I32), _)]));
+        let mut calls = 0;
+        walk(&body[0], 0, &mut |x, _| {
+            if let Expr::Call(_, _) = x {
+                calls += 1;
+            }
+        });
+        assert_eq!(calls, 0);
+        let wasm = crate::backend::encode(&module);
+        assert_eq!(run(&wasm, "f", &[1_000_000.0]).0, 7.0);
+    }
+
+    #[test]
+    fn calls_not_in_tail_position_are_kept() {
+        let body = vec![Expr::If(
+            Some(DataType::I32),
+            Box::new(Expr::Eqz(DataType::I32, Box::new(Expr::LocalGet(0)))),
+            vec![int(0)],
+            vec![Expr::Binary(
+                BinaryOp::Add,
+                DataType::I32,
+                Box::new(Expr::Call(0, vec![minus_one()])),
+                Box::new(int(1)),
+            )],
+        )];
+        let mut module = countdown(body.clone());
+        eliminate_tail_calls(&mut module);
+        assert_eq!(module.functions[0].body, body);
+    }
+
+    #[test]
+    fn branches_out_of_the_function_get_past_the_loop() {
+        // leaves with 7 as soon as the param is 0, else recurses with one less
+        let mut module = countdown(vec![
+            Expr::If(
+                None,
+                Box::new(Expr::Eqz(DataType::I32, Box::new(Expr::LocalGet(0)))),
+                vec![Expr::Sequence(vec![int(7), Expr::Br(1)])],
+                vec![],
+            ),
+            Expr::Call(0, vec![minus_one()]),
+        ]);
+        eliminate_tail_calls(&mut module);
+        let wasm = crate::backend::encode(&module);
+        assert_eq!(run(&wasm, "f", &[5.0]).0, 7.0);
+    }
+
+    #[test]
+    fn params_read_by_later_arguments_are_rebound_together() {
+        let source = "pub fn swap(a, b, n) { if (n == 0) { a } else { swap(b, a, (n - 1)) } }
+pub fn sum_to(n, total) { if (n == 0) { total } else { sum_to((n - 1), (total + n)) } }";
+        let wasm = compile_at(source, OptLevel::Basic);
+        assert_eq!(run(&wasm, "swap", &[1.0, 2.0, 3.0]).0, 2.0);
+        assert_eq!(run(&wasm, "swap", &[1.0, 2.0, 4.0]).0, 1.0);
+        assert_eq!(
+            run(&wasm, "sum_to", &[1_000_000.0, 0.0]).0,
+            500_000_500_000.0
+        );
+    }
+
+    #[test]
+    fn locals_read_before_they_are_set_start_out_as_zero_each_time_around() {
+        // only the first time around sets b, later ones read it as zero
+        let source = "pub fn f(n, acc) {
+  if (n == 3) { b = 1 }
+  if (n == 0) { acc } else { f((n - 1), (acc + b)) }
+}";
+        for level in crate::testing::OPT_LEVELS {
+            let wasm = compile_at(source, *level);
+            assert_eq!(run(&wasm, "f", &[3.0, 0.0]).0, 1.0, "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn deep_mutual_recursion_runs_with_tail_calls() {
+        let source = "pub fn is_even(n: i32) -> i32 { if (n == 0) { 1 } else { is_odd((n - 1)) } }
+pub fn is_odd(n: i32) -> i32 { if (n == 0) { 0 } else { is_even((n - 1)) } }";
+        for opt_level in crate::testing::OPT_LEVELS {
+            let options = crate::compiler::Options {
+                opt_level: *opt_level,
+                features: vec![crate::compiler::Feature::TailCall],
+                ..Default::default()
+            };
+            let wasm = crate::testing::compile(source, &options);
+            assert_eq!(run(&wasm, "is_even", &[1e6]).0, 1.0, "{:?}", opt_level);
+            assert_eq!(run(&wasm, "is_odd", &[1e6 + 1.0]).0, 1.0, "{:?}", opt_level);
+        }
+    }
+}
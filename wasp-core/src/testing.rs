@@ -0,0 +1,99 @@
+//! compiling and running wasp in tests
+
+use crate::compiler::{compile_with_options, OptLevel, Options};
+use wasmi::{Engine, ExternType, Linker, Module, Store, Val};
+
+/// compile the source of a single file
+pub fn compile(source: &str, options: &Options) -> Vec<u8> {
+    let app = crate::parser::parse(source).expect("source parses");
+    compile_with_options(app, options)
+        .expect("source compiles")
+        .wasm
+}
+
+/// compile at an optimization level with everything else left as default
+pub fn compile_at(source: &str, opt_level: OptLevel) -> Vec<u8> {
+    compile(
+        source,
+        &Options {
+            opt_level,
+            ..Options::default()
+        },
+    )
+}
+
+/// every optimization level
+pub const OPT_LEVELS: &[OptLevel] = &[
+    OptLevel::None,
+    OptLevel::Basic,
+    OptLevel::Full,
+    OptLevel::Size,
+    OptLevel::MinSize,
+];
+
+fn to_f64(v: &Val) -> f64 {
+    match v {
+        Val::I32(x) => f64::from(*x),
+        Val::I64(x) => *x as f64,
+        Val::F32(x) => f64::from(f32::from(*x)),
+        Val::F64(x) => f64::from(*x),
+        _ => panic!("not a number"),
+    }
+}
+
+/// call an exported function of a module, giving back its result and the
+/// first argument of every call made to an extern, externs return 0
+pub fn run(wasm: &[u8], function: &str, args: &[f64]) -> (f64, Vec<f64>) {
+    let engine = Engine::default();
+    let module = Module::new(&engine, wasm).expect("module is valid");
+    let mut store = Store::new(&engine, vec![]);
+    let mut linker = <Linker<Vec<f64>>>::new(&engine);
+    for import in module.imports() {
+        if let ExternType::Func(ty) = import.ty() {
+            linker
+                .func_new(
+                    import.module(),
+                    import.name(),
+                    ty.clone(),
+                    |mut caller, params, results| {
+                        if let Some(p) = params.first() {
+                            caller.data_mut().push(to_f64(p));
+                        }
+                        for r in results.iter_mut() {
+                            *r = Val::default(r.ty());
+                        }
+                        Ok(())
+                    },
+                )
+                .expect("extern is defined once");
+        }
+    }
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .and_then(|i| i.start(&mut store))
+        .expect("module instantiates");
+    let f = instance
+        .get_func(&store, function)
+        .unwrap_or_else(|| panic!("{} is exported", function));
+    let ty = f.ty(&store);
+    let params = ty
+        .params()
+        .iter()
+        .zip(args.iter())
+        .map(|(t, a)| match t {
+            wasmi::core::ValType::I32 => Val::I32(*a as i32),
+            wasmi::core::ValType::I64 => Val::I64(*a as i64),
+            wasmi::core::ValType::F32 => Val::F32((*a as f32).into()),
+            _ => Val::F64((*a).into()),
+        })
+        .collect::<Vec<Val>>();
+    let mut results = ty
+        .results()
+        .iter()
+        .map(|t| Val::default(*t))
+        .collect::<Vec<Val>>();
+    f.call(&mut store, &params, &mut results)
+        .expect("function runs");
+    let result = results.first().map(to_f64).unwrap_or(0.0);
+    (result, store.into_data())
+}
@@ -1,38 +1,185 @@
-use failure::Error;
-use std::env;
-use std::fs::metadata;
+use failure::{format_err, Error};
 use std::fs::File;
 use std::io::prelude::*;
 use std::str;
 extern crate clap;
 use clap::{App, AppSettings, Arg, SubCommand};
-use std::fs::OpenOptions;
-use std::io::{BufRead, BufReader};
-use wasp_core::{compiler,parser};
-
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-
-fn write_output(bytes: &[u8], output_file: Option<&str>) -> std::io::Result<()> {
-    if output_file.is_none() {
-        let path = env::current_dir().unwrap();
-        let output_file = format!(
-            "{}.wasm",
-            String::from(path.file_name().unwrap().to_str().unwrap())
-        );
-        let mut buffer = File::create(output_file)?;
-        buffer.write_all(bytes)?;
+use wasp_core::{compiler, modules, parser};
+
+mod manifest;
+use manifest::{Dependency, Manifest};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn write_output(bytes: &[u8], output_file: &str) -> std::io::Result<()> {
+    let mut buffer = File::create(output_file)?;
+    buffer.write_all(bytes)?;
+    Ok(())
+}
+
+/// a source file to build and the module it becomes
+struct SourceFile {
+    file: String,
+    module: modules::Module,
+}
+
+fn module_path(package: &str, relative_file: &str) -> Vec<String> {
+    let mut path = vec![package.to_string()];
+    path.extend(
+        relative_file
+            .trim_end_matches(".w")
+            .split('/')
+            .map(|s| s.to_string()),
+    );
+    path
+}
+
+fn run(files: Vec<SourceFile>, options: &compiler::Options) -> Result<compiler::Output, Error> {
+    let mut modules = vec![];
+    for mut f in files {
+        let content = std::fs::read_to_string(&f.file)?;
+        f.module.app =
+            parser::parse_file(&f.file, &content).map_err(|e| format_err!("{}: {}", f.file, e))?;
+        modules.push(f.module);
+    }
+    let app = modules::link(modules)?;
+    compiler::compile_with_options(app, options)
+}
+
+/// gather the wasp files found within a folder as paths relative to it, in a
+/// stable order. Files ignored by the project's .gitignore files, build
+/// output, nested projects and the folders in `skip` (dependencies that live
+/// inside the project) are skipped.
+fn source_files(folder: &str, skip: &[String]) -> Result<Vec<String>, Error> {
+    use ignore::WalkBuilder;
+
+    let root = std::path::PathBuf::from(folder);
+    // compare folders by where they really are however they were written
+    let skip = skip
+        .iter()
+        .filter_map(|f| std::fs::canonicalize(f).ok())
+        .collect::<Vec<std::path::PathBuf>>();
+    let mut files = vec![];
+    let walker = WalkBuilder::new(folder)
+        .require_git(false)
+        // only what the project says, so every machine builds the same files
+        .git_global(false)
+        .git_exclude(false)
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if !is_dir || entry.depth() == 0 {
+                return true;
+            }
+            if entry.depth() == 1
+                && (entry.file_name() == "vendor" || entry.file_name() == "target")
+            {
+                return false;
+            }
+            if let Ok(path) = std::fs::canonicalize(entry.path()) {
+                if skip.contains(&path) {
+                    return false;
+                }
+            }
+            !manifest::is_project_dir(entry.path())
+        })
+        .build();
+    for entry in walker {
+        let entry = entry?;
+        let is_file = entry.file_type().map(|t| t.is_file()).unwrap_or(false);
+        if is_file && entry.path().extension().map(|e| e == "w").unwrap_or(false) {
+            let relative = entry
+                .path()
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| entry.path());
+            let components = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<String>>();
+            files.push(components.join("/"));
+        }
+    }
+    // sort on our normalized paths so order doesn't depend on the platform
+    files.sort();
+    Ok(files)
+}
+
+fn git_clone(location: &str, destination: &str) -> Result<(), Error> {
+    let output = std::process::Command::new("git")
+        .args(["clone", location, destination])
+        .output()
+        .map_err(|e| format_err!("failed to execute git: {}", e))?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "git clone of \"{}\" into \"{}\" failed ({})\n{}",
+            location,
+            destination,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
     }
     Ok(())
 }
 
+fn git_checkout(folder: &str, reference: &str) -> Result<(), Error> {
+    let output = std::process::Command::new("git")
+        .args(["-C", folder, "checkout", reference])
+        .output()
+        .map_err(|e| format_err!("failed to execute git: {}", e))?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "git checkout of \"{}\" in \"{}\" failed ({})\n{}",
+            reference,
+            folder,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(())
+}
+
+/// add a git dependency to the project in a folder and clone it into its
+/// vendor folder, leaving the manifest as it was if that fails
+fn add(folder: &std::path::Path, name: &str, location: &str) -> Result<(), Error> {
+    let manifest_file = manifest::manifest_file(folder).ok_or_else(|| {
+        format_err!(
+            "no {} or {} found in {}",
+            manifest::MANIFEST_FILE,
+            manifest::LEGACY_MANIFEST_FILE,
+            folder.display()
+        )
+    })?;
+    let manifest_path = folder.join(manifest_file);
+    let original = std::fs::read_to_string(&manifest_path)?;
+    manifest::add_dependency(folder, name, location)
+        .map_err(|e| format_err!("couldn't write to {}: {}", manifest_file, e))?;
+    let destination = folder.join("vendor").join(name);
+    if let Err(e) = git_clone(location, &destination.to_string_lossy()) {
+        // undo our edit so the manifest doesn't reference a missing dependency
+        std::fs::write(&manifest_path, original)?;
+        return Err(format_err!("could not add dependency \"{}\": {}", name, e));
+    }
+    Ok(())
+}
 
+fn main() {
+    if let Err(e) = run_command() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
 
-fn run(content: &str) -> Result<Vec<u8>, Error> {
-    let app = parser::parse(content)?;
-    compiler::compile(app)
+/// a number of pages of memory given on the command line
+fn pages(value: Option<&str>) -> Result<Option<u32>, Error> {
+    match value {
+        Some(v) => v
+            .parse::<u32>()
+            .map(Some)
+            .map_err(|_| format_err!("\"{}\" is not a number of pages", v)),
+        None => Ok(None),
+    }
 }
 
-fn main() -> Result<(), Error> {
+fn run_command() -> Result<(), Error> {
     let matches = App::new("wasp")
         .setting(AppSettings::ArgRequiredElseHelp)
         .version(VERSION)
@@ -52,6 +199,71 @@ fn main() -> Result<(), Error> {
                         .long("emscripten")
                         .short("e")
                         .help("Sets the level of verbosity"),
+                )
+                .arg(
+                    Arg::with_name("features")
+                        .long("features")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .help("web assembly proposals to use, e.g. tail-call"),
+                )
+                .arg(
+                    Arg::with_name("opt-level")
+                        .long("opt-level")
+                        .short("O")
+                        .takes_value(true)
+                        .possible_values(&["0", "1", "2", "s", "z"])
+                        .help("how much to optimize, from 0 (not at all) to 2 (the default), or s and z for size"),
+                )
+                .arg(
+                    Arg::with_name("size-report")
+                        .long("size-report")
+                        .help("print how many bytes each function, kind of data and section takes"),
+                )
+                .arg(
+                    Arg::with_name("initial-memory")
+                        .long("initial-memory")
+                        .takes_value(true)
+                        .value_name("PAGES")
+                        .help("initial size of linear memory in 64KiB pages"),
+                )
+                .arg(
+                    Arg::with_name("max-memory")
+                        .long("max-memory")
+                        .takes_value(true)
+                        .value_name("PAGES")
+                        .help("maximum size of linear memory in 64KiB pages"),
+                )
+                .arg(
+                    Arg::with_name("import-memory")
+                        .long("import-memory")
+                        .help("use linear memory given by the host as env.memory"),
+                )
+                .arg(
+                    Arg::with_name("export-memory-name")
+                        .long("export-memory-name")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .help("the name linear memory is exported as, memory by default"),
+                )
+                .arg(
+                    Arg::with_name("shared-memory")
+                        .long("shared-memory")
+                        .help("make linear memory shared so threads can use it"),
+                )
+                .arg(
+                    Arg::with_name("memory-map")
+                        .long("memory-map")
+                        .takes_value(true)
+                        .min_values(0)
+                        .possible_values(&["text", "json"])
+                        .help("print the address of every string literal, static and struct before the heap, as text (the default) or json"),
+                )
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .help("leave out test functions and debug names, and report the size of each section"),
                 ),
         )
         .subcommand(
@@ -85,60 +297,122 @@ fn main() -> Result<(), Error> {
         )
         .get_matches();
 
-    if let Some(_matches) = matches.subcommand_matches("build") {
-        use walkdir::WalkDir;
+    if let Some(matches) = matches.subcommand_matches("build") {
+        let manifest = Manifest::load()?;
+        let mut features = manifest.build.features()?;
+        for name in matches.values_of("features").into_iter().flatten() {
+            features.push(
+                compiler::Feature::from_name(name)
+                    .ok_or_else(|| format_err!("unknown feature \"{}\"", name))?,
+            );
+        }
 
+        // dependencies come first in the order they are declared
         let mut files = vec![];
-        for entry in WalkDir::new("./") {
-            let entry = entry.unwrap();
-            let f = entry.path().display().to_string();
-            if f.ends_with(".w") {
-                let md = metadata(f.clone()).unwrap();
-                if md.is_file() {
-                    files.push(f);
-                }
+        for (name, dependency) in manifest.dependencies.iter() {
+            let folder = dependency.source_dir(name);
+            if !std::path::Path::new(&folder).exists() {
+                return Err(format_err!(
+                    "dependency \"{}\" not found at \"{}\", try running wasp vendor",
+                    name,
+                    folder
+                ));
+            }
+            for f in source_files(&folder, &[])? {
+                files.push(SourceFile {
+                    file: format!("{}/{}", folder, f),
+                    module: modules::Module {
+                        path: module_path(name, &f),
+                        root: false,
+                        entry: false,
+                        app: wasp_core::ast::App { children: vec![] },
+                    },
+                });
             }
         }
 
-        let mut packages = vec![];
-
-        if std::path::Path::new("project.wasp").exists() {
-            let file = File::open("project.wasp")?;
-            for line in BufReader::new(file).lines() {
-                let l = line?;
-                let v: Vec<&str> = l.split(' ').collect();
-                packages.push(v[0].to_string())
+        // then the files of this package starting with its entry file
+        let dependency_folders = manifest
+            .dependencies
+            .iter()
+            .map(|(name, dependency)| dependency.source_dir(name))
+            .collect::<Vec<String>>();
+        let mut project_files = source_files(".", &dependency_folders)?
+            .into_iter()
+            .filter(|f| manifest.is_source_file(f))
+            .collect::<Vec<String>>();
+        let entry = manifest.package.entry();
+        match project_files.iter().position(|f| f == entry) {
+            Some(p) => {
+                let entry = project_files.remove(p);
+                project_files.insert(0, entry);
             }
+            None => return Err(format_err!("entry file \"{}\" not found", entry)),
+        }
+        for f in project_files {
+            files.push(SourceFile {
+                module: modules::Module {
+                    path: module_path(&manifest.package.name, &f),
+                    root: true,
+                    entry: f == entry,
+                    app: wasp_core::ast::App { children: vec![] },
+                },
+                file: f,
+            });
         }
 
-        files.sort_by(|a, b| {
-            if a.starts_with("./vendor/") {
-                if b.starts_with("./vendor/") {
-                    let sa = a.split('/').collect::<Vec<&str>>()[2];
-                    let sb = b.split('/').collect::<Vec<&str>>()[2];
-                    let pa = packages
-                        .iter()
-                        .position(|r| r == sa)
-                        .unwrap_or(std::usize::MAX);
-                    let pb = packages
-                        .iter()
-                        .position(|r| r == sb)
-                        .unwrap_or(std::usize::MAX);
-                    return pa.cmp(&pb);
-                }
-                return std::cmp::Ordering::Less;
+        let opt_level = match matches.value_of("opt-level") {
+            Some(level) => compiler::OptLevel::from_name(level)
+                .ok_or_else(|| format_err!("unknown optimization level \"{}\"", level))?,
+            None => compiler::OptLevel::default(),
+        };
+        let options = compiler::Options {
+            initial_memory: pages(matches.value_of("initial-memory"))?
+                .or(manifest.build.initial_memory),
+            max_memory: pages(matches.value_of("max-memory"))?.or(manifest.build.max_memory),
+            import_memory: matches.is_present("import-memory")
+                || manifest.build.import_memory.unwrap_or(false),
+            export_memory_name: matches
+                .value_of("export-memory-name")
+                .map(|s| s.to_string())
+                .or_else(|| manifest.build.export_memory_name.clone()),
+            shared_memory: matches.is_present("shared-memory")
+                || manifest.build.shared_memory.unwrap_or(false),
+            lints: manifest.lints.clone(),
+            features,
+            opt_level,
+            release: matches.is_present("release"),
+        };
+        let output = run(files, &options)?;
+        for w in output.warnings.iter() {
+            eprintln!("warning[{}]: {}", w.lint, w);
+        }
+        if matches.is_present("verbose") && output.peephole_saved > 0 {
+            println!(
+                "peephole optimizations saved {} byte{}",
+                output.peephole_saved,
+                if output.peephole_saved == 1 { "" } else { "s" }
+            );
+        }
+        if matches.is_present("size-report") {
+            println!(
+                "{}",
+                wasp_core::size::size_report(&output.module, &output.wasm)
+            );
+        } else if options.release {
+            for (section, size) in wasp_core::backend::section_sizes(&output.wasm) {
+                println!("{:>10} {:>8} bytes", section, size);
             }
-            std::cmp::Ordering::Equal
-        });
-
-        let mut contents = "".to_string();
-        for file in files {
-            let c = std::fs::read_to_string(&file).unwrap();
-            contents = format!("{}\n{}", &contents, &c).to_string();
+            println!("{:>10} {:>8} bytes", "total", output.wasm.len());
         }
-
-        let output = run(&contents)?;
-        write_output(&output, None)?;
+        if matches.is_present("memory-map") {
+            let map = wasp_core::memory_map::memory_map(&output.module);
+            match matches.value_of("memory-map") {
+                Some("json") => println!("{}", map.to_json()),
+                _ => println!("{}", map),
+            }
+        }
+        write_output(&output.wasm, &manifest.output_file())?;
         return Ok(());
     };
 
@@ -149,28 +423,27 @@ fn main() -> Result<(), Error> {
                 std::fs::create_dir(f)?;
                 let mut file = File::create(format!("{}/{}", f, "main.w"))?;
                 file.write_all(include_bytes!("static/main.w"))?;
-                let mut file = File::create(format!("{}/{}", f, "project.wasp"))?;
-                file.write_all(include_bytes!("static/project.wasp"))?;
+                let mut file_project = File::create(format!("{}/{}", f, manifest::MANIFEST_FILE))?;
+                let project = include_str!("static/Wasp.toml").replace("PROJECT_NAME", f);
+                file_project.write_all(project.as_bytes())?;
                 let mut file = File::create(format!("{}/{}", f, "index.html"))?;
                 let mut idx = include_str!("static/index.html").to_string();
-                idx = idx.replace("PROJECT_NAME", &f);
-                file.write_all((&idx).as_bytes())?;
+                idx = idx.replace("PROJECT_NAME", f);
+                file.write_all(idx.as_bytes())?;
                 let no_std = matches.is_present("no-std");
                 if !no_std {
-                    std::process::Command::new("git")
-                        .args(&[
-                            "clone",
-                            "git@github.com:wasplang/std.git",
-                            &format!("{}/vendor/{}", f, "std"),
-                        ])
-                        .output()
-                        .expect("failed to execute process");
+                    let std_location = "git@github.com:wasplang/std.git";
+                    writeln!(file_project, "std = {{ git = \"{}\" }}", std_location)?;
+                    if let Err(e) = git_clone(std_location, &format!("{}/vendor/{}", f, "std")) {
+                        // don't leave a half created project behind
+                        std::fs::remove_dir_all(f)?;
+                        return Err(format_err!("could not add standard library: {}", e));
+                    }
                     println!("added standard library");
                 }
                 println!("created package");
             } else {
-                println!("directory \"{}\" already exists", f);
-                std::process::exit(1);
+                return Err(format_err!("directory \"{}\" already exists", f));
             }
         }
         return Ok(());
@@ -179,35 +452,185 @@ fn main() -> Result<(), Error> {
     if let Some(matches) = matches.subcommand_matches("add") {
         let name = matches.value_of("NAME").expect("no name");
         let location = matches.value_of("LOCATION").expect("no location");
-        let mut file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open("project.wasp")
-            .unwrap();
-
-        if let Err(e) = writeln!(file, "{} {}", name, location) {
-            eprintln!("Couldn't write to file: {}", e);
-        }
-        std::process::Command::new("git")
-            .args(&["clone", location, &format!("vendor/{}", name)])
-            .output()
-            .expect("failed to execute process");
+        add(std::path::Path::new("."), name, location)?;
         println!("added dependency");
     }
 
     if matches.subcommand_matches("vendor").is_some() {
-        std::fs::remove_dir_all("vendor")?;
-        let file = File::open("project.wasp")?;
-        for line in BufReader::new(file).lines() {
-            let l = line?;
-            let v: Vec<&str> = l.split(' ').collect();
-            std::process::Command::new("git")
-                .args(&["clone", v[1], &format!("vendor/{}", v[0])])
-                .output()
-                .expect("failed to execute process");
-            println!("vendoring \"{}\"", v[0]);
+        let manifest = Manifest::load()?;
+        if std::path::Path::new("vendor").exists() {
+            std::fs::remove_dir_all("vendor")?;
+        }
+        for (name, dependency) in manifest.dependencies.iter() {
+            if let Dependency::Git { url, .. } = dependency {
+                println!("vendoring \"{}\"", name);
+                let folder = dependency.source_dir(name);
+                git_clone(url, &folder)
+                    .and_then(|_| match dependency.git_ref() {
+                        Some(r) => git_checkout(&folder, r),
+                        None => Ok(()),
+                    })
+                    .map_err(|e| format_err!("could not vendor \"{}\": {}", name, e))?;
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an empty folder to lay out a project in
+    fn project(name: &str) -> std::path::PathBuf {
+        let folder = std::env::temp_dir().join(format!("wasp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn write(folder: &std::path::Path, file: &str, content: &str) {
+        let path = folder.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn source_files_skip_dependencies_ignored_files_and_build_output() {
+        let folder = project("sources");
+        write(&folder, "main.w", "");
+        write(&folder, "util/strings.w", "");
+        write(&folder, "libs/mylib/list.w", "");
+        write(&folder, "scratch/notes.w", "");
+        write(&folder, "vendor/std/list.w", "");
+        write(&folder, "nested/Wasp.toml", "");
+        write(&folder, "nested/main.w", "");
+        write(&folder, ".gitignore", "scratch/\n");
+        let root = folder.to_string_lossy().to_string();
+        let files = source_files(&root, &[format!("{}/libs/mylib", root)]).unwrap();
+        assert_eq!(files, vec!["main.w", "util/strings.w"]);
+        let files = source_files(&format!("{}/libs/mylib", root), &[]).unwrap();
+        assert_eq!(files, vec!["list.w"]);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    /// a git repository with one commit to clone dependencies from
+    fn repository(folder: &std::path::Path) {
+        write(folder, "list.w", "pub fn cons(h, t) { h }");
+        for args in [
+            vec!["init", "-q"],
+            vec!["add", "."],
+            vec![
+                "-c",
+                "user.name=wasp",
+                "-c",
+                "user.email=wasp@localhost",
+                "commit",
+                "-q",
+                "-m",
+                "list",
+            ],
+        ] {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(folder)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn failed_adds_leave_the_manifest_unchanged() {
+        let folder = project("failed-add");
+        let missing = folder.join("missing").to_string_lossy().to_string();
+        for (file, content) in [
+            (manifest::MANIFEST_FILE, "[package]\nname = \"x\"\n"),
+            (manifest::LEGACY_MANIFEST_FILE, "std ../std\n"),
+        ] {
+            let _ = std::fs::remove_file(folder.join(manifest::MANIFEST_FILE));
+            write(&folder, file, content);
+            let error = add(&folder, "lib", &missing).unwrap_err().to_string();
+            assert!(
+                error.starts_with("could not add dependency \"lib\": git clone of"),
+                "{}",
+                error
+            );
+            assert_eq!(std::fs::read_to_string(folder.join(file)).unwrap(), content);
+            assert!(!folder.join("vendor/lib").exists());
+        }
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn added_dependencies_are_cloned_and_recorded() {
+        let folder = project("add");
+        repository(&folder.join("lib"));
+        write(
+            &folder,
+            manifest::MANIFEST_FILE,
+            "[package]\nname = \"x\"\n",
+        );
+        let location = folder.join("lib").to_string_lossy().to_string();
+        add(&folder, "lib", &location).unwrap();
+        assert!(folder.join("vendor/lib/list.w").exists());
+        let manifest = Manifest::parse(
+            &std::fs::read_to_string(folder.join(manifest::MANIFEST_FILE)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(manifest.dependencies[0].0, "lib");
+        // adding it again is refused before anything is cloned
+        let error = add(&folder, "lib", &location).unwrap_err().to_string();
+        assert!(
+            error.contains("dependency \"lib\" already exists"),
+            "{}",
+            error
+        );
+        let error = add(&folder.join("vendor"), "lib", &location)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("no Wasp.toml or project.wasp found in"),
+            "{}",
+            error
+        );
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn git_failures_give_what_git_said() {
+        let folder = project("git");
+        let missing = folder.join("missing").to_string_lossy().to_string();
+        let destination = folder.join("clone").to_string_lossy().to_string();
+        let error = git_clone(&missing, &destination).unwrap_err().to_string();
+        assert!(
+            error.starts_with(&format!(
+                "git clone of \"{}\" into \"{}\" failed",
+                missing, destination
+            )),
+            "{}",
+            error
+        );
+        assert!(error.contains("does not exist"), "{}", error);
+
+        repository(&folder.join("lib"));
+        let lib = folder.join("lib").to_string_lossy().to_string();
+        git_clone(&lib, &destination).unwrap();
+        git_checkout(&destination, "HEAD").unwrap();
+        let error = git_checkout(&destination, "v9.9.9")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with(&format!(
+                "git checkout of \"v9.9.9\" in \"{}\" failed",
+                destination
+            )),
+            "{}",
+            error
+        );
+        assert!(error.contains("did not match"), "{}", error);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,492 @@
+use failure::{format_err, Error};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use wasp_core::compiler::Feature;
+use wasp_core::lint;
+
+pub const MANIFEST_FILE: &str = "Wasp.toml";
+pub const LEGACY_MANIFEST_FILE: &str = "project.wasp";
+
+#[derive(Debug, Clone)]
+pub enum Dependency {
+    Git {
+        url: String,
+        rev: Option<String>,
+        tag: Option<String>,
+        branch: Option<String>,
+    },
+    Path(String),
+}
+
+impl Dependency {
+    /// the folder wasp source files of this dependency are read from
+    pub fn source_dir(&self, name: &str) -> String {
+        match self {
+            Dependency::Git { .. } => format!("vendor/{}", name),
+            Dependency::Path(p) => p.clone(),
+        }
+    }
+
+    /// the git reference to checkout after cloning, if any
+    pub fn git_ref(&self) -> Option<&str> {
+        match self {
+            Dependency::Git {
+                rev, tag, branch, ..
+            } => rev
+                .as_ref()
+                .or(tag.as_ref())
+                .or(branch.as_ref())
+                .map(|s| s.as_str()),
+            Dependency::Path(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    pub version: Option<String>,
+    pub entry: Option<String>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Package {
+    /// the file compiled first in this package
+    pub fn entry(&self) -> &str {
+        self.entry.as_deref().unwrap_or("main.w")
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Build {
+    pub output: Option<String>,
+    pub initial_memory: Option<u32>,
+    pub max_memory: Option<u32>,
+    pub import_memory: Option<bool>,
+    pub export_memory_name: Option<String>,
+    pub shared_memory: Option<bool>,
+    /// the web assembly the module is built for, `mvp` followed by the
+    /// proposals it may use, e.g. `mvp+tail-call`
+    pub target: Option<String>,
+}
+
+impl Build {
+    /// the proposals beyond the MVP the target allows
+    pub fn features(&self) -> Result<Vec<Feature>, Error> {
+        let target = match &self.target {
+            Some(t) => t,
+            None => return Ok(vec![]),
+        };
+        let mut names = target.split('+');
+        if names.next() != Some("mvp") {
+            return Err(format_err!(
+                "target \"{}\" must start with \"mvp\", e.g. \"mvp+tail-call\"",
+                target
+            ));
+        }
+        names
+            .map(|name| {
+                Feature::from_name(name)
+                    .ok_or_else(|| format_err!("unknown feature \"{}\" in target", name))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub package: Package,
+    pub dependencies: Vec<(String, Dependency)>,
+    pub build: Build,
+    /// lint levels of the project, e.g. `unused_variables = "allow"`
+    pub lints: Vec<(String, lint::Level)>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPackage {
+    name: Option<String>,
+    version: Option<String>,
+    entry: Option<String>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDependency {
+    git: Option<String>,
+    rev: Option<String>,
+    tag: Option<String>,
+    branch: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    package: Option<RawPackage>,
+    dependencies: Option<toml::value::Table>,
+    build: Option<Build>,
+    lints: Option<toml::value::Table>,
+}
+
+fn default_package_name() -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "main".to_string())
+}
+
+fn default_package() -> Package {
+    Package {
+        name: default_package_name(),
+        version: None,
+        entry: None,
+        include: vec!["**/*.w".to_string()],
+        exclude: vec![],
+    }
+}
+
+impl Manifest {
+    /// load the manifest of the project in the current directory, falling back
+    /// to a legacy project.wasp or to defaults if there is neither
+    pub fn load() -> Result<Manifest, Error> {
+        if Path::new(MANIFEST_FILE).exists() {
+            let content = std::fs::read_to_string(MANIFEST_FILE)?;
+            Manifest::parse(&content)
+                .map_err(|e| format_err!("could not read {}: {}", MANIFEST_FILE, e))
+        } else if Path::new(LEGACY_MANIFEST_FILE).exists() {
+            Manifest::load_legacy(LEGACY_MANIFEST_FILE)
+        } else {
+            Ok(Manifest {
+                package: default_package(),
+                dependencies: vec![],
+                build: Build::default(),
+                lints: vec![],
+            })
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Manifest, Error> {
+        let raw: RawManifest = toml::from_str(content)?;
+        let mut package = default_package();
+        if let Some(p) = raw.package {
+            if let Some(name) = p.name {
+                package.name = name;
+            }
+            package.version = p.version;
+            package.entry = p.entry;
+            if let Some(include) = p.include {
+                package.include = include;
+            }
+            if let Some(exclude) = p.exclude {
+                package.exclude = exclude;
+            }
+        }
+        for pattern in package.include.iter().chain(package.exclude.iter()) {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format_err!("invalid pattern \"{}\": {}", pattern, e))?;
+        }
+
+        let mut dependencies = vec![];
+        for (name, value) in raw.dependencies.unwrap_or_default() {
+            let dependency = match value {
+                // a plain string is shorthand for a git url
+                toml::Value::String(url) => Dependency::Git {
+                    url,
+                    rev: None,
+                    tag: None,
+                    branch: None,
+                },
+                v => {
+                    let d: RawDependency = v
+                        .try_into()
+                        .map_err(|e| format_err!("dependency \"{}\": {}", name, e))?;
+                    match (d.git, d.path) {
+                        (Some(url), None) => {
+                            let refs = [&d.rev, &d.tag, &d.branch];
+                            if refs.iter().filter(|r| r.is_some()).count() > 1 {
+                                return Err(format_err!(
+                                    "dependency \"{}\" can only specify one of rev, tag or branch",
+                                    name
+                                ));
+                            }
+                            Dependency::Git {
+                                url,
+                                rev: d.rev,
+                                tag: d.tag,
+                                branch: d.branch,
+                            }
+                        }
+                        (None, Some(path)) => {
+                            if d.rev.is_some() || d.tag.is_some() || d.branch.is_some() {
+                                return Err(format_err!(
+                                    "path dependency \"{}\" can't specify a git reference",
+                                    name
+                                ));
+                            }
+                            Dependency::Path(path)
+                        }
+                        _ => {
+                            return Err(format_err!(
+                                "dependency \"{}\" must specify either git or path",
+                                name
+                            ))
+                        }
+                    }
+                }
+            };
+            dependencies.push((name, dependency));
+        }
+
+        let mut lints = vec![];
+        for (id, value) in raw.lints.unwrap_or_default() {
+            if !lint::is_lint(&id) {
+                return Err(format_err!("unknown lint \"{}\"", id));
+            }
+            let level = value
+                .as_str()
+                .and_then(lint::Level::from_name)
+                .ok_or_else(|| {
+                    format_err!(
+                        "lint \"{}\" must be one of \"allow\", \"warn\" or \"deny\"",
+                        id
+                    )
+                })?;
+            lints.push((id, level));
+        }
+
+        let build = raw.build.unwrap_or_default();
+        build.features()?;
+
+        Ok(Manifest {
+            package,
+            dependencies,
+            build,
+            lints,
+        })
+    }
+
+    /// read a project.wasp file made of `name location` lines
+    fn load_legacy(file_name: &str) -> Result<Manifest, Error> {
+        let file = File::open(file_name)
+            .map_err(|e| format_err!("could not open {}: {}", file_name, e))?;
+        let mut dependencies = vec![];
+        for line in BufReader::new(file).lines() {
+            let l = line?;
+            if l.trim().is_empty() {
+                continue;
+            }
+            let v: Vec<&str> = l.split_whitespace().collect();
+            if v.len() != 2 {
+                return Err(format_err!(
+                    "invalid line in {}, expected \"name location\": {}",
+                    file_name,
+                    l
+                ));
+            }
+            dependencies.push((
+                v[0].to_string(),
+                Dependency::Git {
+                    url: v[1].to_string(),
+                    rev: None,
+                    tag: None,
+                    branch: None,
+                },
+            ));
+        }
+        Ok(Manifest {
+            package: default_package(),
+            dependencies,
+            build: Build::default(),
+            lints: vec![],
+        })
+    }
+
+    pub fn output_file(&self) -> String {
+        match &self.build.output {
+            Some(o) => o.clone(),
+            None => format!("{}.wasm", self.package.name),
+        }
+    }
+
+    /// is this project relative path (e.g. `src/main.w`) a source file of the package
+    pub fn is_source_file(&self, path: &str) -> bool {
+        let matches = |patterns: &Vec<String>| {
+            patterns.iter().any(|p| {
+                glob::Pattern::new(p)
+                    .map(|p| p.matches(path))
+                    .unwrap_or(false)
+            })
+        };
+        path == self.package.entry()
+            || (matches(&self.package.include) && !matches(&self.package.exclude))
+    }
+}
+
+/// the content of a manifest with a git dependency added after the others,
+/// leaving the rest as written
+fn with_git_dependency(content: &str, name: &str, location: &str) -> Result<String, Error> {
+    let mut manifest = content.parse::<toml_edit::DocumentMut>()?;
+    let dependencies = manifest
+        .entry("dependencies")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or_else(|| format_err!("dependencies is not a table"))?;
+    if dependencies.contains_key(name) {
+        return Err(format_err!("dependency \"{}\" already exists", name));
+    }
+    let mut dependency = toml_edit::InlineTable::new();
+    dependency.insert("git", location.into());
+    dependencies.insert(name, toml_edit::value(dependency));
+    Ok(manifest.to_string())
+}
+
+/// add a git dependency to the manifest of the project in a folder
+pub fn add_dependency(folder: &Path, name: &str, location: &str) -> Result<(), Error> {
+    if folder.join(MANIFEST_FILE).exists() {
+        let content = std::fs::read_to_string(folder.join(MANIFEST_FILE))?;
+        let content = with_git_dependency(&content, name, location)
+            .map_err(|e| format_err!("could not update {}: {}", MANIFEST_FILE, e))?;
+        std::fs::write(folder.join(MANIFEST_FILE), content)?;
+    } else {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(folder.join(LEGACY_MANIFEST_FILE))
+            .map_err(|e| {
+                format_err!(
+                    "could not open {} or {}: {}",
+                    MANIFEST_FILE,
+                    LEGACY_MANIFEST_FILE,
+                    e
+                )
+            })?;
+        writeln!(file, "{} {}", name, location)?;
+    }
+    Ok(())
+}
+
+/// does this folder contain a wasp project of its own
+pub fn is_project_dir(folder: &Path) -> bool {
+    folder.join(MANIFEST_FILE).exists() || folder.join(LEGACY_MANIFEST_FILE).exists()
+}
+
+/// the manifest file of the project in a folder, if it has one
+pub fn manifest_file(folder: &Path) -> Option<&'static str> {
+    if folder.join(MANIFEST_FILE).exists() {
+        Some(MANIFEST_FILE)
+    } else if folder.join(LEGACY_MANIFEST_FILE).exists() {
+        Some(LEGACY_MANIFEST_FILE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"# my project
+[package]
+name = "myproject"
+version = "0.1.0"
+entry = "src/main.w"
+exclude = ["experiments/**"]
+
+[build]
+output = "out.wasm"
+initial_memory = 3
+target = "mvp+tail-call"
+
+[dependencies]
+# the standard library
+std = { git = "https://example.com/std.git", tag = "v0.1.0" }
+baz = { path = "../baz" }
+"#;
+
+    #[test]
+    fn manifests_are_parsed() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        assert_eq!(manifest.package.name, "myproject");
+        assert_eq!(manifest.package.entry(), "src/main.w");
+        assert_eq!(manifest.package.include, vec!["**/*.w"]);
+        assert_eq!(manifest.output_file(), "out.wasm");
+        assert_eq!(manifest.build.initial_memory, Some(3));
+        assert_eq!(manifest.build.features().unwrap(), vec![Feature::TailCall]);
+        let names = manifest
+            .dependencies
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["std", "baz"]);
+        assert_eq!(manifest.dependencies[0].1.git_ref(), Some("v0.1.0"));
+        assert_eq!(manifest.dependencies[1].1.source_dir("baz"), "../baz");
+        assert!(manifest.is_source_file("src/lib/list.w"));
+        assert!(!manifest.is_source_file("experiments/old.w"));
+    }
+
+    #[test]
+    fn unknown_keys_and_targets_are_errors() {
+        for content in [
+            "[package]\nnmae = \"typo\"",
+            "[build]\ninitial_memroy = 2",
+            "[biuld]\noutput = \"out.wasm\"",
+            "[dependencies]\nstd = { git = \"url\", tagg = \"v1\" }",
+            "[build]\ntarget = \"tail-call\"",
+            "[build]\ntarget = \"mvp+threads\"",
+        ] {
+            assert!(Manifest::parse(content).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn lints_are_read_in_order() {
+        let content = "[package]\nname = \"x\"\n\n[lints]\nwarnings = \"deny\"\nunused_variables = \"allow\"\n";
+        let manifest = Manifest::parse(content).unwrap();
+        assert_eq!(
+            manifest.lints,
+            vec![
+                ("warnings".to_string(), lint::Level::Deny),
+                ("unused_variables".to_string(), lint::Level::Allow),
+            ]
+        );
+        assert_eq!(
+            Manifest::parse("[lints]\nunused_fnuctions = \"deny\"")
+                .unwrap_err()
+                .to_string(),
+            "unknown lint \"unused_fnuctions\""
+        );
+        assert!(Manifest::parse("[lints]\nwarnings = \"forbid\"").is_err());
+    }
+
+    #[test]
+    fn added_dependencies_keep_the_rest_of_the_manifest() {
+        let content = with_git_dependency(MANIFEST, "bar", "https://example.com/bar.git").unwrap();
+        assert_eq!(
+            content,
+            format!(
+                "{}bar = {{ git = \"https://example.com/bar.git\" }}\n",
+                MANIFEST
+            )
+        );
+        let manifest = Manifest::parse(&content).unwrap();
+        assert_eq!(manifest.dependencies.len(), 3);
+        assert!(with_git_dependency(&content, "bar", "elsewhere").is_err());
+    }
+
+    #[test]
+    fn dependencies_are_added_to_manifests_without_any() {
+        let content = "[package]\nname = \"x\" # named\n";
+        let content = with_git_dependency(content, "std", "url").unwrap();
+        assert_eq!(
+            content,
+            "[package]\nname = \"x\" # named\n\n[dependencies]\nstd = { git = \"url\" }\n"
+        );
+    }
+}